@@ -1,97 +1,443 @@
 
-use std::{env, fs};
+use std::{env, fs, io::{self, BufRead, BufReader, Read}};
 use itertools::Itertools;
 
 fn main() {
-    let (filename, _sample_param)= if env::args().nth(1).map_or(false, |s| s == "-s") {
-        ("sample.txt", 0)
+    let args: Vec<String> = env::args().collect();
+    let filename = if args.iter().any(|s| s == "-s") {
+        "sample.txt"
     } else {
-        ("input.txt", 0)
+        "input.txt"
+    };
+    let validator = match args.iter().position(|s| s == "-p").and_then(|i| args.get(i + 1)) {
+        Some(path) => Validator::from_config(&fs::read_to_string(path)
+            .unwrap_or_else(|_| panic!("Something went wrong reading the pairs {}", path))),
+        None => Validator::standard(),
     };
 
-    // let mut mappings = HashMap::new();
-    // mappings.insert('{',('}', 1197));
-    // mappings.insert('<',('>', 25137));
-    // mappings.insert('(',(')', 3));
-    // mappings.insert('[',(']', 57));
+    if args.iter().any(|s| s == "--stream") {
+        let file = fs::File::open(filename)
+            .unwrap_or_else(|_| panic!("Something went wrong opening the file {}", filename));
+        let (mut error_score, mut completed) = (0, 0);
+        validate_stream(&validator, file, |line, start, status| {
+            if let LineStatus::Incomplete { completion } = &status {
+                match validator.completion_score(completion) {
+                    Some(_) => completed += 1,
+                    None => println!("line {} (byte {}): completion score overflows", line, start),
+                }
+                if completion.len() > 80 {
+                    println!("line {} (byte {}): incomplete, needs {} closers", line, start, completion.len());
+                    return;
+                }
+            }
+            match status {
+                LineStatus::Valid => (),
+                LineStatus::Corrupted { column, found, .. } | LineStatus::UnmatchedCloser { column, found } => {
+                    error_score += validator.error_score(found);
+                    println!("line {} (byte {}): {:?}", line, start + column as u64 - 1, status);
+                },
+                LineStatus::UnknownCharacter { column, .. } =>
+                    println!("line {} (byte {}): {:?}", line, start + column as u64 - 1, status),
+                LineStatus::Incomplete { .. } => println!("line {} (byte {}): {:?}", line, start, status),
+            }
+        }).unwrap_or_else(|e| panic!("Something went wrong reading the file {}: {}", filename, e));
+        let median = if completed == 0 {
+            0
+        } else {
+            select_completion_score(&validator, filename, completed / 2)
+                .unwrap_or_else(|e| panic!("Something went wrong reading the file {}: {}", filename, e))
+        };
+        println!("part_one {}", error_score);
+        println!("part_two {}", median);
+        return;
+    }
 
     let contents = fs::read_to_string(filename)
         .unwrap_or_else(|_| panic!("Something went wrong reading the file {}", filename));
     let input_lines = contents.split('\n').map(|line| line.chars().collect_vec()).collect_vec();
-    println!("part_one {}", part_one(&input_lines));
-    println!("part_two {}", part_two(&input_lines));
+    let statuses = input_lines.iter().map(|line| validator.validate(line)).collect_vec();
+    let mut repaired_lines = vec![];
+    for (line, (chars, status)) in input_lines.iter().zip(&statuses).enumerate() {
+        let repair = validator.repair(chars);
+        if *status != LineStatus::Valid {
+            println!("line {}: {:?}", line + 1, status);
+            println!("  edits {:?}, repaired: {}", repair.edits, repair.line);
+        }
+        repaired_lines.push(repair.line);
+    }
+    println!("part_one {}", part_one(&validator, &statuses));
+    println!("part_two {}", part_two(&validator, &statuses));
+
+    if args.iter().any(|s| s == "--fix") {
+        fs::write(filename, repaired_lines.join("\n"))
+            .unwrap_or_else(|_| panic!("Could not rewrite {}", filename));
+        println!("Rewrote {} with every line repaired", filename);
+    }
 }
 
-fn _is_opening(candidate: &char) -> bool {
-    matches!(candidate, '{'| '('| '<' |'[')
+#[derive(Debug, Clone)]
+struct Pair {
+    open: char,
+    close: char,
+    // Points for a corrupted line stopped by this closer
+    error_score: usize,
+    // Points for needing this closer to complete a line
+    completion_score: usize,
 }
-fn get_score(closing: &char) -> i32 {
-    match closing{
-        '}' => 1197,
-        ')' => 3, 
-        '>' => 25137,
-        ']' => 57,
-        _ => 0
-    }
+
+#[derive(Debug, PartialEq, Eq)]
+enum LineStatus {
+    Valid,
+    // Columns are 1-based
+    Corrupted { column: usize, expected: char, found: char },
+    UnmatchedCloser { column: usize, found: char },
+    UnknownCharacter { column: usize, found: char },
+    Incomplete { completion: String },
 }
-fn get_score2(closing: &char) -> usize {
-    match closing{
-        '}' => 3,
-        ')' => 1, 
-        '>' => 4,
-        ']' => 2,
-        _ => 0
-    }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Edit {
+    // Columns are 1-based, in the line as it is when the edit is made
+    Insert { column: usize, char: char },
+    Delete { column: usize, found: char },
+    Replace { column: usize, found: char, with: char },
 }
 
-fn get_closing(opening: &char) -> Option<char> {
-    match opening {
-        '{' => Some('}'),
-        '(' => Some(')'),
-        '<' => Some('>'),
-        '[' => Some(']'),
-        _ => None 
+impl Edit {
+    fn apply(&self, line: &[char]) -> Vec<char> {
+        let mut line = line.to_vec();
+        match *self {
+            Edit::Insert { column, char } => line.insert(column - 1, char),
+            Edit::Delete { column, .. } => { line.remove(column - 1); },
+            Edit::Replace { column, with, .. } => line[column - 1] = with,
+        }
+        line
     }
 }
 
-fn part_one(lines: &[Vec<char>]) -> i32 {
-    lines.iter().map(|line| {
-        // println!("Looking at line {:?}", line);
+#[derive(Debug)]
+struct Repair {
+    edits: Vec<Edit>,
+    // The edited line with its completion appended
+    line: String,
+}
+
+struct Validator {
+    pairs: Vec<Pair>,
+}
+
+impl Validator {
+    fn standard() -> Validator {
+        Validator::from_config("( ) 3 1\n[ ] 57 2\n{ } 1197 3\n< > 25137 4")
+    }
+
+    // One pair per line: "open close error_score completion_score"
+    fn from_config(config: &str) -> Validator {
+        let pairs = config.split('\n').filter(|line| !line.trim().is_empty()).map(|line| {
+            let (open, close, error_score, completion_score) = line.split_whitespace().collect_tuple()
+                .unwrap_or_else(|| panic!("Expected open, close and two scores, found {}", line));
+            let single = |s: &str| s.chars().exactly_one().unwrap_or_else(|_| panic!("{} is not one character", s));
+            Pair {
+                open: single(open),
+                close: single(close),
+                error_score: error_score.parse().unwrap(),
+                completion_score: completion_score.parse().unwrap(),
+            }
+        }).collect_vec();
+        let symbols = pairs.iter().flat_map(|pair| vec![pair.open, pair.close]).collect_vec();
+        assert!(symbols.iter().unique().count() == symbols.len(), "A character is used by more than one pair");
+        Validator { pairs }
+    }
+
+    fn closing(&self, opening: char) -> Option<char> {
+        self.pairs.iter().find(|pair| pair.open == opening).map(|pair| pair.close)
+    }
+
+    fn closer(&self, closing: char) -> Option<&Pair> {
+        self.pairs.iter().find(|pair| pair.close == closing)
+    }
+
+    fn validate(&self, line: &[char]) -> LineStatus {
+        // Closers still owed, innermost last
         let mut stack: Vec<char> = vec![];
-        for char in line {
-            if let Some(closing) = get_closing(char) {
+        for (i, &char) in line.iter().enumerate() {
+            let column = i + 1;
+            if let Some(closing) = self.closing(char) {
                 stack.push(closing);
-            } else if let Some(last) = stack.pop() {
-                if last != *char {
-                    // println!("Got error with char {}", char);
-                    return get_score(char);
-                }
+            } else if self.closer(char).is_none() {
+                return LineStatus::UnknownCharacter { column, found: char };
             } else {
-                println!("Popping empty list?");
+                match stack.pop() {
+                    Some(expected) if expected != char => return LineStatus::Corrupted { column, expected, found: char },
+                    Some(_) => (),
+                    None => return LineStatus::UnmatchedCloser { column, found: char },
+                }
+            }
+        }
+        if stack.is_empty() {
+            LineStatus::Valid
+        } else {
+            LineStatus::Incomplete { completion: stack.iter().rev().collect() }
+        }
+    }
+
+    // Cheapest way to make line[i..j] balanced, for every i <= j. Either line[i]
+    // gets a partner of its own (one edit), or it is paired with some line[k]
+    // with at most a replacement on each side, and the parts inside and after
+    // the pair are balanced in turn.
+    fn balance_costs(&self, line: &[char]) -> Vec<Vec<usize>> {
+        let n = line.len();
+        let mut costs = vec![vec![0; n + 1]; n + 1];
+        for length in 1..=n {
+            for i in 0..=n - length {
+                let j = i + length;
+                costs[i][j] = (i + 1..j)
+                    .map(|k| self.pair_cost(line[i], line[k]) + costs[i + 1][k] + costs[k + 1][j])
+                    .fold(1 + costs[i + 1][j], usize::min);
             }
         }
-        0 
+        costs
+    }
+
+    // Replacements needed for `open` and `close` to be a matching pair
+    fn pair_cost(&self, open: char, close: char) -> usize {
+        match self.closing(open) {
+            Some(closing) => (closing != close) as usize,
+            None if self.closer(close).is_some() => 1,
+            None => 2,
+        }
+    }
+
+    // What to do to line[i] for it to pair with line[k], as (index, edit) pairs
+    fn pair_edits(&self, line: &[char], i: usize, k: usize, edits: &mut Vec<(usize, Edit)>) {
+        let replace = |column: usize, with: char| Edit::Replace { column, found: line[column], with };
+        match self.closing(line[i]) {
+            Some(closing) if closing != line[k] => edits.push((k, replace(k, closing))),
+            Some(_) => (),
+            None => match self.closer(line[k]) {
+                Some(pair) => edits.push((i, replace(i, pair.open))),
+                None => {
+                    let pair = self.pairs.iter().find(|pair| pair.open == line[k]).unwrap_or(&self.pairs[0]);
+                    edits.push((i, replace(i, pair.open)));
+                    edits.push((k, replace(k, pair.close)));
+                },
+            },
+        }
+    }
+
+    // Gives line[i] a partner of its own, or drops it if it isn't a bracket
+    fn lone_edit(&self, line: &[char], i: usize) -> Edit {
+        if let Some(closing) = self.closing(line[i]) {
+            Edit::Insert { column: i + 1, char: closing }
+        } else if let Some(pair) = self.closer(line[i]) {
+            Edit::Insert { column: i, char: pair.open }
+        } else {
+            Edit::Delete { column: i, found: line[i] }
+        }
+    }
+
+    // Edits that balance line[i..j] at the cost in `costs`, preferring pairs
+    fn balance_edits(&self, line: &[char], costs: &[Vec<usize>], i: usize, j: usize, edits: &mut Vec<(usize, Edit)>) {
+        if i == j {
+            return;
+        }
+        let paired = (i + 1..j).find(|&k| {
+            self.pair_cost(line[i], line[k]) + costs[i + 1][k] + costs[k + 1][j] == costs[i][j]
+        });
+        match paired {
+            Some(k) => {
+                self.pair_edits(line, i, k, edits);
+                self.balance_edits(line, costs, i + 1, k, edits);
+                self.balance_edits(line, costs, k + 1, j, edits);
+            },
+            None => {
+                edits.push((i, self.lone_edit(line, i)));
+                self.balance_edits(line, costs, i + 1, j, edits);
+            },
+        }
+    }
+
+    // Fewest edits (insert, delete or replace a character) that leave a line
+    // with no errors, so it only needs completing, then appends the
+    // completion. A line that can be completed is balanced runs with openers
+    // left between them, so on top of `balance_costs` each suffix line[i..]
+    // either leaves line[i] open for free, if it is an opener, or starts with
+    // a balanced run. O(n^3) in the line length.
+    fn repair(&self, line: &[char]) -> Repair {
+        let n = line.len();
+        let costs = self.balance_costs(line);
+        let mut suffix_costs = vec![0; n + 1];
+        for i in (0..n).rev() {
+            let open = self.closing(line[i]).map(|_| suffix_costs[i + 1]);
+            suffix_costs[i] = (i + 1..=n).map(|k| costs[i][k] + suffix_costs[k]).chain(open).min().unwrap();
+        }
+
+        // Edits against the original columns, 0-based
+        let mut planned = vec![];
+        let mut i = 0;
+        while i < n {
+            if self.closing(line[i]).is_some() && suffix_costs[i] == suffix_costs[i + 1] {
+                i += 1;
+                continue;
+            }
+            let k = (i + 1..=n).find(|&k| costs[i][k] + suffix_costs[k] == suffix_costs[i]).unwrap();
+            self.balance_edits(line, &costs, i, k, &mut planned);
+            i = k;
+        }
+
+        // Apply left to right, moving each column by what earlier edits added
+        // or removed
+        planned.sort_by_key(|(index, _)| *index);
+        let mut edited = line.to_vec();
+        let mut edits = vec![];
+        let mut shift: isize = 0;
+        for (_, edit) in planned {
+            let moved = |column: usize| (column as isize + shift + 1) as usize;
+            let edit = match edit {
+                Edit::Insert { column, char } => Edit::Insert { column: moved(column), char },
+                Edit::Delete { column, found } => Edit::Delete { column: moved(column), found },
+                Edit::Replace { column, found, with } => Edit::Replace { column: moved(column), found, with },
+            };
+            shift += match edit {
+                Edit::Insert { .. } => 1,
+                Edit::Delete { .. } => -1,
+                Edit::Replace { .. } => 0,
+            };
+            edited = edit.apply(&edited);
+            edits.push(edit);
+        }
+
+        let mut repaired: String = edited.iter().collect();
+        match self.validate(&edited) {
+            LineStatus::Incomplete { completion } => repaired += &completion,
+            LineStatus::Valid => (),
+            status => panic!("Repair left {:?} in {}", status, repaired),
+        }
+        Repair { edits, line: repaired }
+    }
+
+    fn error_score(&self, found: char) -> usize {
+        self.closer(found).map_or(0, |pair| pair.error_score)
+    }
+
+    // None if the score doesn't fit, which very deep lines can manage
+    fn completion_score(&self, completion: &str) -> Option<usize> {
+        completion.chars().try_fold(0usize, |acc, closing| {
+            acc.checked_mul(5)?.checked_add(self.closer(closing).map_or(0, |pair| pair.completion_score))
+        })
+    }
+}
+
+// An unmatched closer is the first illegal character too, so it scores the same
+fn part_one(validator: &Validator, statuses: &[LineStatus]) -> usize {
+    statuses.iter().map(|status| match status {
+        LineStatus::Corrupted { found, .. } | LineStatus::UnmatchedCloser { found, .. } => validator.error_score(*found),
+        _ => 0,
     }).sum()
 }
-fn part_two(lines: &[Vec<char>]) -> usize {
-    let filtered = lines.iter().map(|line| {
-        // println!("Looking at line {:?}", line);
-        let mut stack: Vec<char> = vec![];
-        for char in line {
-            if let Some(closing) = get_closing(char) {
-                stack.push(closing);
-            } else if let Some(last) = stack.pop() {
-                if last != *char {
-                    return None;
+
+fn part_two(validator: &Validator, statuses: &[LineStatus]) -> usize {
+    let filtered = statuses.iter().filter_map(|status| match status {
+        LineStatus::Incomplete { completion } => validator.completion_score(completion),
+        _ => None,
+    }).sorted().collect_vec();
+    println!("filtered: {:?}", filtered);
+    filtered.get(filtered.len() / 2).copied().unwrap_or(0)
+}
+
+fn finish_line(stack: &mut Vec<u8>, error: Option<LineStatus>) -> LineStatus {
+    let status = match error {
+        Some(error) => error,
+        None if stack.is_empty() => LineStatus::Valid,
+        None => LineStatus::Incomplete { completion: stack.iter().rev().map(|&byte| byte as char).collect() },
+    };
+    stack.clear();
+    status
+}
+
+// Validates a byte at a time straight from the reader, only holding the
+// closers still owed on the current line, so memory follows nesting depth
+// rather than line length. Calls `on_line` with the 1-based line number, the
+// byte offset the line starts at and its status, whose columns count bytes.
+// Pairs have to be ASCII.
+fn validate_stream<R, F>(validator: &Validator, reader: R, mut on_line: F) -> io::Result<()>
+where R: Read, F: FnMut(usize, u64, LineStatus) {
+    let mut closing_for: [Option<u8>; 256] = [None; 256];
+    let mut is_closer = [false; 256];
+    for pair in &validator.pairs {
+        assert!(pair.open.is_ascii() && pair.close.is_ascii(), "Streaming needs ASCII pairs");
+        closing_for[pair.open as usize] = Some(pair.close as u8);
+        is_closer[pair.close as usize] = true;
+    }
+
+    let mut reader = BufReader::with_capacity(1 << 16, reader);
+    let mut stack: Vec<u8> = vec![];
+    let mut error: Option<LineStatus> = None;
+    let (mut line, mut start, mut offset) = (1, 0u64, 0u64);
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+        let length = buffer.len();
+        for &byte in buffer {
+            if byte == b'\n' {
+                on_line(line, start, finish_line(&mut stack, error.take()));
+                line += 1;
+                start = offset + 1;
+            } else if error.is_none() {
+                let column = (offset - start + 1) as usize;
+                let found = byte as char;
+                if let Some(closing) = closing_for[byte as usize] {
+                    stack.push(closing);
+                } else if !is_closer[byte as usize] {
+                    error = Some(LineStatus::UnknownCharacter { column, found });
+                } else {
+                    match stack.pop() {
+                        Some(expected) if expected != byte =>
+                            error = Some(LineStatus::Corrupted { column, expected: expected as char, found }),
+                        Some(_) => (),
+                        None => error = Some(LineStatus::UnmatchedCloser { column, found }),
+                    }
+                }
+                if error.is_some() {
+                    // Nothing after the first error matters, so stop holding the stack
+                    stack = vec![];
                 }
-            } else {
-                println!("Popping empty list?");
             }
+            offset += 1;
         }
-        Some(stack.iter().rev().fold(0, |acc, closing_character| {
-            acc * 5 + get_score2(closing_character)
-        }))
-    }).flatten().sorted().collect_vec();
-    println!("filtered: {:?}", filtered);
-    *filtered.get(filtered.len() / 2).unwrap()
-}
\ No newline at end of file
+        reader.consume(length);
+    }
+    if offset > start {
+        on_line(line, start, finish_line(&mut stack, error));
+    }
+    Ok(())
+}
+
+// The `rank`th smallest completion score (from 0) in the file, without
+// holding every score: each pass over the file counts the next byte of the
+// scores that share the bytes picked so far, so this reads the file once per
+// byte of a usize and only ever keeps 256 counters.
+fn select_completion_score(validator: &Validator, filename: &str, mut rank: usize) -> io::Result<usize> {
+    let mut prefix = 0usize;
+    for shift in (0..usize::BITS).step_by(8).rev() {
+        let mut buckets = [0usize; 256];
+        let high = |score: usize| score.checked_shr(shift + 8).unwrap_or(0);
+        validate_stream(validator, fs::File::open(filename)?, |_, _, status| {
+            if let LineStatus::Incomplete { completion } = status {
+                match validator.completion_score(&completion) {
+                    Some(score) if high(score) == high(prefix) => buckets[score >> shift & 0xff] += 1,
+                    _ => (),
+                }
+            }
+        })?;
+        let mut byte = 0;
+        while rank >= buckets[byte] {
+            rank -= buckets[byte];
+            byte += 1;
+        }
+        prefix |= byte << shift;
+    }
+    Ok(prefix)
+}
@@ -1,112 +1,163 @@
 
-use std::{env, fs};
+use std::{collections::HashMap, env, fs};
 use itertools::Itertools;
-use std::collections::HashSet;
-
-type Point = (usize, usize);
 
 fn main() {
-    let (filename, _sample_param)= if env::args().nth(1).map_or(false, |s| s == "-s") {
-        ("sample.txt", 0)
+    let args: Vec<String> = env::args().collect();
+    let filename = if args.iter().any(|s| s == "-s") {
+        "sample.txt"
     } else {
-        ("input.txt", 0)
+        "input.txt"
     };
+    let flag = |name: &str| args.iter().position(|s| s == name).and_then(|i| args.get(i + 1));
+    let steps: usize = flag("-n").map_or(100, |n| n.parse().expect("-n takes a number of steps"));
+    let horizon: u64 = flag("-H").map_or(1_000_000_000_000, |h| h.parse().expect("-H takes a number of steps"));
 
     let contents = fs::read_to_string(filename)
         .unwrap_or_else(|_| panic!("Something went wrong reading the file {}", filename));
-    let mut input = contents.split('\n').map(|line| line.chars().map(|c| c.to_digit(10).unwrap()).collect_vec()).collect_vec();
+    let input = contents.split('\n')
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.chars().map(|c| c.to_digit(10).unwrap()).collect_vec())
+        .collect_vec();
+    let start = Grid::new(&input);
     println!("Before steps");
-    display(&input);
-
-    let mut total_flashed = 0;
-    let mut i = 1;
-
-    while {
-        println!("\n\nProcessing step {}", i);
-        let (new_input, flashed) = step(input);
-        input = new_input;
-        total_flashed += flashed;
-        println!("\n\nafter step {}: {} flashed, {} total_flashed", i, flashed, total_flashed);
-        i+= 1;
-        display(&input);
-        flashed != 100
-    } {}
+    start.display();
+
+    let mut grid = start.clone();
+    let total_flashed = grid.run(steps);
+    println!("\n\nafter step {}: {} total_flashed", steps, total_flashed);
+    grid.display();
+    println!("\nflashes per cell:");
+    for row in grid.flashes.chunks(grid.width) {
+        println!("{}", row.iter().map(|count| format!("{:>4}", count)).join(""));
+    }
+
+    match start.clone().first_synchronized(1_000_000) {
+        Some(step) => println!("\nall {} flashed on step {}", start.energy.len(), step),
+        None => println!("\nno step within 1000000 where all {} flashed", start.energy.len()),
+    }
+
+    match start.clone().find_cycle(1_000_000) {
+        Some(cycle) => {
+            println!("pre-period {} steps, period {} steps", cycle.start, cycle.period);
+            println!("total flashes after {} steps: {}", horizon, cycle.total_flashes(horizon));
+        },
+        None => println!("no cycle within 1000000 steps"),
+    }
 }
 
-fn display(vals: &Vec<Vec<u32>>) {
-    vals.iter().for_each(|line| {
-        println!("{:?}", line);
-    });
+#[derive(Debug)]
+struct Cycle {
+    // Steps before the first state that repeats
+    start: usize,
+    period: usize,
+    // Flashes in the first n steps, for n up to start + period
+    totals: Vec<usize>,
 }
 
-fn get_neighbor_indices(pt: &Point) -> HashSet<Point>{
-    let mut out = HashSet::new();
-    let (x, y) = pt;
-    let x_plus= x + 1;
-    let y_plus  = y + 1;
-    let x_minus = x.checked_sub(1);
-    let y_minus = y.checked_sub(1);
-    out.insert((x_plus, *y));
-    out.insert((*x, y_plus));
-    out.insert((x_plus, y_plus));
-    if let Some(x_)= x_minus {
-        out.insert((x_, *y));
-        out.insert((x_, y_plus));
-    }
-    if let Some(y_)= y_minus {
-        out.insert((*x, y_));
-        out.insert((x_plus, y_));
-        if let Some(x_)= x_minus {
-            out.insert((x_, y_));
+impl Cycle {
+    fn total_flashes(&self, steps: u64) -> u128 {
+        if steps < self.totals.len() as u64 {
+            return self.totals[steps as usize] as u128;
         }
+        let (start, period) = (self.start as u64, self.period as u64);
+        let per_cycle = (self.totals[self.start + self.period] - self.totals[self.start]) as u128;
+        let (cycles, remainder) = ((steps - start) / period, ((steps - start) % period) as usize);
+        self.totals[self.start + remainder] as u128 + cycles as u128 * per_cycle
     }
-    // println!("Neighbors to ({},{}), {:?}", x, y, out);
-    out
 }
 
-fn step(input: Vec<Vec<u32>>) -> (Vec<Vec<u32>>, usize) {
-    let mut incremented = input.iter().map(|row| {
-        row.iter().map(|value| {
-            value + 1        
-        }).collect_vec()
-    }).collect_vec();
-
-    // display(&incremented);
-
-    let mut flashed = HashSet::new();
-    let mut prev_count;
-    while {
-        prev_count = flashed.len();
-        let all_flashed :HashSet<Point> = incremented.iter().enumerate().map(|(x, row)| {
-            row.iter().enumerate().filter_map(|(y, value)| {
-                if value > &9 {
-                    Some((x, y))
-                } else {
-                    None
-                }
-            }).collect_vec()
-        }).flatten().collect();
-
-        let new_flashed= all_flashed.difference(&flashed);
-        // println!("new flashed count: {}, new_flashed{:?}", new_flashed.clone().count(), new_flashed);
-
-        new_flashed.for_each(|pt| {
-            get_neighbor_indices(pt).iter().for_each(|(x,y)| {
-                incremented.get_mut(*x).map(|row| row.get_mut(*y).map(|value| { 
-                    // println!("Incrementing neighbor ({},{}), currently {}",*x, *y, value); 
-                    *value+=1
-                }));
-            });
+#[derive(Debug, Clone)]
+struct Grid {
+    width: usize,
+    height: usize,
+    // Row-major energy levels
+    energy: Vec<u32>,
+    // How many times each cell has flashed
+    flashes: Vec<usize>,
+    steps: usize,
+}
+
+impl Grid {
+    fn new(input: &[Vec<u32>]) -> Grid {
+        let width = input.first().map_or(0, |row| row.len());
+        assert!(input.iter().all(|row| row.len() == width), "Rows have different lengths");
+        let energy = input.concat();
+        Grid { width, height: input.len(), flashes: vec![0; energy.len()], energy, steps: 0 }
+    }
+
+    fn display(&self) {
+        self.energy.chunks(self.width).for_each(|line| {
+            println!("{:?}", line);
         });
+    }
+
+    // All eight surrounding cells that are on the grid
+    fn neighbors(&self, index: usize) -> impl Iterator<Item = usize> {
+        let (width, height) = (self.width as isize, self.height as isize);
+        let (row, col) = ((index / self.width) as isize, (index % self.width) as isize);
+        (-1..=1).cartesian_product(-1..=1)
+            .filter(|&(dr, dc)| (dr, dc) != (0, 0))
+            .map(move |(dr, dc)| (row + dr, col + dc))
+            .filter(move |&(r, c)| r >= 0 && c >= 0 && r < height && c < width)
+            .map(move |(r, c)| (r * width + c) as usize)
+    }
 
-        flashed = all_flashed;
-        // println!("all_flashed count {:?}, prev_count {}", flashed.len(), prev_count);
-        // display(&incremented);
-        flashed.len() > prev_count 
-    } {}
+    // Returns how many flashed. Each cell that crosses 9 goes on the worklist
+    // exactly once, so a step is linear in the grid plus the flashes.
+    fn step(&mut self) -> usize {
+        let mut worklist = vec![];
+        for (index, value) in self.energy.iter_mut().enumerate() {
+            *value += 1;
+            if *value == 10 {
+                worklist.push(index);
+            }
+        }
+        let mut flashed = 0;
+        while let Some(index) = worklist.pop() {
+            flashed += 1;
+            self.flashes[index] += 1;
+            for neighbor in self.neighbors(index).collect_vec() {
+                self.energy[neighbor] += 1;
+                if self.energy[neighbor] == 10 {
+                    worklist.push(neighbor);
+                }
+            }
+        }
+        self.energy.iter_mut().filter(|value| **value > 9).for_each(|value| *value = 0);
+        self.steps += 1;
+        flashed
+    }
 
-    incremented.iter_mut().for_each(|row| row.iter_mut().for_each(|value| if *value > 9 { *value = 0 }));
+    // Total flashes over the next `steps` steps
+    fn run(&mut self, steps: usize) -> usize {
+        (0..steps).map(|_| self.step()).sum()
+    }
 
+    // Steps until the grid returns to a state it has been in before. Flash
+    // totals from then on repeat every period, whatever the horizon. Steps
+    // are counted from the grid as it is now, not from its first step.
+    fn find_cycle(&mut self, limit: usize) -> Option<Cycle> {
+        let mut seen: HashMap<Vec<u32>, usize> = HashMap::new();
+        let mut totals = vec![0];
+        for taken in 0..=limit {
+            if let Some(&start) = seen.get(&self.energy) {
+                return Some(Cycle { start, period: taken - start, totals });
+            }
+            seen.insert(self.energy.clone(), taken);
+            let flashed = self.step();
+            totals.push(totals.last().unwrap() + flashed);
+        }
+        None
+    }
 
-    (incremented, flashed.len())
+    // The first step (counting from the start) on which every cell flashes
+    fn first_synchronized(&mut self, limit: usize) -> Option<usize> {
+        while self.steps < limit {
+            if self.step() == self.energy.len() {
+                return Some(self.steps);
+            }
+        }
+        None
+    }
 }
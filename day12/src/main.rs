@@ -1,7 +1,7 @@
 
 use std::{env, fs};
 use itertools::Itertools;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{HashMap, HashSet};
 type Network<'a> = HashMap<&'a str, Cave<'a>>;
 
 #[derive(Debug)]
@@ -19,73 +19,293 @@ impl Cave<'_> {
     }
 }
 
-// Return unique path count
-fn bfs<'a>(network: &Network, start: &'a str, end: &'a str) -> Option<usize> {
-    let mut queue: VecDeque<(&str, Vec<&str>)> = VecDeque::new();
-    let mut paths: Vec<Vec<&str>> = vec![];
-    queue.push_back((start, vec![start]));
-    while !queue.is_empty()  {
-        let (node, current_path) = queue.pop_front()?;
-        let cave = network.get(node)?;
-        // println!("Visiting cave for path: {} {:?}", node, current_path);
-        for neighbor in &cave.neighbors {
-            let neighbor_cave = network.get(neighbor)?;
-            if !neighbor_cave.small || !current_path.contains(neighbor) {
-                let mut new_path = current_path.clone();
-                new_path.push(neighbor);
-                if neighbor == &end {
-                    paths.push(new_path);
+// The network with caves numbered
+struct Graph<'a> {
+    names: Vec<&'a str>,
+    small: Vec<bool>,
+    adjacency: Vec<Vec<usize>>,
+    start: usize,
+    end: usize,
+}
+
+// Which paths count. Every cave has a number of visits it allows, one for
+// small caves and unlimited for big ones unless overridden. On top of that the
+// path may spend `revisits` extra visits past those limits, except in strict
+// caves. Part one is the default policy, part two is the default with one
+// revisit.
+#[derive(Debug, Clone)]
+struct Policy {
+    limits: HashMap<usize, usize>,
+    revisits: usize,
+    // Caves the revisit budget can't be spent on, start by default
+    strict: HashSet<usize>,
+    // Caves every path has to pass through
+    mandatory: HashSet<usize>,
+}
+
+impl Policy {
+    fn new(graph: &Graph) -> Policy {
+        Policy {
+            limits: HashMap::new(),
+            revisits: 0,
+            strict: [graph.start].iter().copied().collect(),
+            mandatory: HashSet::new(),
+        }
+    }
+
+    fn with_revisits(mut self, revisits: usize) -> Policy {
+        self.revisits = revisits;
+        self
+    }
+
+    fn with_limit(mut self, cave: usize, limit: usize) -> Policy {
+        self.limits.insert(cave, limit);
+        self
+    }
+
+    fn forbid(mut self, cave: usize) -> Policy {
+        self.limits.insert(cave, 0);
+        self.strict.insert(cave);
+        self
+    }
+
+    fn require(mut self, cave: usize) -> Policy {
+        self.mandatory.insert(cave);
+        self
+    }
+
+    // None for unlimited
+    fn limit(&self, graph: &Graph, cave: usize) -> Option<usize> {
+        match self.limits.get(&cave) {
+            Some(limit) => Some(*limit),
+            None if graph.small[cave] => Some(1),
+            None => None,
+        }
+    }
+
+    // Whether one more visit is allowed, and if so whether it spends a revisit
+    fn visit(&self, graph: &Graph, cave: usize, visits: usize, spent: usize) -> Option<bool> {
+        match self.limit(graph, cave) {
+            Some(limit) if visits >= limit => {
+                if spent < self.revisits && !self.strict.contains(&cave) {
+                    Some(true)
                 } else {
-                    queue.push_back((neighbor, new_path));
+                    None
                 }
-            }
+            },
+            _ => Some(false),
         }
     }
-    Some(paths.len())
 }
 
-// Return unique path count
-fn bfs2<'a>(network: &Network, start: &'a str, end: &'a str) -> Option<usize> {
-    let mut queue: VecDeque<(&str, Vec<&str>, bool)> = VecDeque::new();
-    let mut paths: Vec<Vec<&str>> = vec![];
-    queue.push_back((start, vec![start], false));
-    while !queue.is_empty()  {
-        let (node, current_path, reuse_small) = queue.pop_front()?;
-        let cave = network.get(node)?;
-        // println!("Visiting cave for path: {} {} {:?}", node, reuse_small, current_path);
-        for neighbor in &cave.neighbors {
-            let neighbor_cave = network.get(neighbor)?;
-            let mut new_path = current_path.clone();
-            new_path.push(neighbor);
-            if neighbor == &end {
-                paths.push(new_path);
-            } else if neighbor != &start {
-                if neighbor_cave.small { 
-                    if !current_path.contains(neighbor) {
-                        queue.push_back((neighbor, new_path, reuse_small));
-                    } else if !reuse_small {
-                        queue.push_back((neighbor, new_path, true));
+impl<'a> Graph<'a> {
+    fn new(network: &Network<'a>, start: &str, end: &str) -> Graph<'a> {
+        let names = network.keys().copied().sorted().collect_vec();
+        let small = names.iter().map(|name| network[name].small).collect_vec();
+        let mut graph = Graph { names, small, adjacency: vec![], start: 0, end: 0 };
+        graph.adjacency = graph.names.iter()
+            .map(|name| network[name].neighbors.iter().map(|neighbor| graph.id(neighbor)).sorted().collect_vec())
+            .collect_vec();
+        graph.start = graph.id(start);
+        graph.end = graph.id(end);
+        graph
+    }
+
+    fn id(&self, name: &str) -> usize {
+        self.names.iter().position(|n| *n == name).unwrap_or_else(|| panic!("No cave {}", name))
+    }
+
+    // Unlimited caves next to each other mean paths can loop forever
+    fn check_finite(&self, policy: &Policy) {
+        for (cave, neighbors) in self.adjacency.iter().enumerate() {
+            let unlimited = |cave: usize| policy.limit(self, cave).is_none();
+            if let Some(&neighbor) = neighbors.iter().find(|&&n| unlimited(cave) && unlimited(n)) {
+                panic!("Caves {} and {} have no visit limit and are connected, so there are infinitely many paths",
+                    self.names[cave], self.names[neighbor]);
+            }
+        }
+    }
+
+    // Counts paths without building any of them. The number of ways to finish
+    // only depends on where we are, the visits so far to every cave with a
+    // limit or requirement, and how much of the revisit budget is spent, so
+    // each of those is worked out once.
+    fn count_paths(&self, policy: &Policy) -> u128 {
+        self.check_finite(policy);
+        let tracked = (0..self.names.len())
+            .map(|cave| policy.limit(self, cave).is_some() || policy.mandatory.contains(&cave))
+            .collect_vec();
+        let mut visits = vec![0; self.names.len()];
+        match policy.visit(self, self.start, 0, 0) {
+            Some(false) => visits[self.start] = 1,
+            _ => return 0,
+        }
+        let mut memo = HashMap::new();
+        self.count_from(self.start, &mut visits, 0, policy, &tracked, &mut memo)
+    }
+
+    fn count_from(&self, cave: usize, visits: &mut Vec<usize>, spent: usize, policy: &Policy,
+                  tracked: &[bool], memo: &mut HashMap<(usize, Vec<usize>, usize), u128>) -> u128 {
+        if cave == self.end {
+            return if policy.mandatory.iter().all(|&required| visits[required] > 0) { 1 } else { 0 };
+        }
+        let key = (cave, visits.clone(), spent);
+        if let Some(&count) = memo.get(&key) {
+            return count;
+        }
+        let mut count = 0;
+        for &next in &self.adjacency[cave] {
+            let revisit = match policy.visit(self, next, visits[next], spent) {
+                Some(revisit) => revisit,
+                None => continue,
+            };
+            // Untracked caves are unlimited, so their visits don't change anything
+            let bump = tracked[next] && (visits[next] == 0 || policy.limit(self, next).is_some());
+            if bump {
+                visits[next] += 1;
+            }
+            count += self.count_from(next, visits, spent + revisit as usize, policy, tracked, memo);
+            if bump {
+                visits[next] -= 1;
+            }
+        }
+        memo.insert(key, count);
+        count
+    }
+
+    // Graphviz source for the network. Big caves are grey boxes, small caves
+    // ellipses. Edges on `highlight` are drawn red, and with `heatmap` every
+    // edge is labelled with how many times paths cross it and thickened to
+    // match.
+    fn to_dot(&self, highlight: &[usize], heatmap: Option<&HashMap<(usize, usize), usize>>) -> String {
+        let edge = |a: usize, b: usize| (a.min(b), a.max(b));
+        let highlighted: HashSet<(usize, usize)> = highlight.iter().tuple_windows().map(|(a, b)| edge(*a, *b)).collect();
+        let hottest = heatmap.and_then(|counts| counts.values().max().copied()).unwrap_or(0).max(1);
+
+        let mut dot = String::from("graph caves {\n");
+        for (cave, name) in self.names.iter().enumerate() {
+            let style = if self.small[cave] {
+                "shape=ellipse"
+            } else {
+                "shape=box, style=filled, fillcolor=lightgrey"
+            };
+            let ends = if cave == self.start || cave == self.end { ", peripheries=2" } else { "" };
+            dot += &format!("    \"{}\" [{}{}];\n", name, style, ends);
+        }
+        for (a, neighbors) in self.adjacency.iter().enumerate() {
+            for &b in neighbors.iter().filter(|&&b| a < b) {
+                let mut attributes = vec![];
+                if let Some(counts) = heatmap {
+                    let count = counts.get(&(a, b)).copied().unwrap_or(0);
+                    attributes.push(format!("label=\"{}\"", count));
+                    attributes.push(format!("penwidth={:.2}", 1.0 + 7.0 * count as f64 / hottest as f64));
+                }
+                if highlighted.contains(&(a, b)) {
+                    attributes.push("color=red".to_string());
+                    if heatmap.is_none() {
+                        attributes.push("penwidth=3".to_string());
                     }
-                } else {
-                    queue.push_back((neighbor, new_path, reuse_small));
                 }
+                dot += &format!("    \"{}\" -- \"{}\"", self.names[a], self.names[b]);
+                if !attributes.is_empty() {
+                    dot += &format!(" [{}]", attributes.join(", "));
+                }
+                dot += ";\n";
+            }
+        }
+        dot += "}\n";
+        dot
+    }
+
+    // Times each edge (lower id first) is crossed over every path. This walks
+    // the paths one by one, so it is only practical when there aren't too many.
+    fn edge_traffic(&self, policy: &Policy) -> HashMap<(usize, usize), usize> {
+        let mut traffic = HashMap::new();
+        for path in self.paths(policy) {
+            for (a, b) in path.iter().map(|name| self.id(name)).tuple_windows() {
+                *traffic.entry((a.min(b), a.max(b))).or_insert(0) += 1;
             }
         }
+        traffic
+    }
+
+    fn paths<'g>(&'g self, policy: &'g Policy) -> Paths<'g, 'a> {
+        self.check_finite(policy);
+        let mut visits = vec![0; self.names.len()];
+        let stack = match policy.visit(self, self.start, 0, 0) {
+            Some(false) => {
+                visits[self.start] = 1;
+                vec![(self.start, 0, false)]
+            },
+            _ => vec![],
+        };
+        Paths { graph: self, policy, stack, visits, spent: 0 }
     }
-    Some(paths.len())
 }
 
+// Depth-first walk over every path from start to end, one at a time. Only the
+// current path is held, as (cave, index of the next neighbour to try, whether
+// getting there spent a revisit).
+struct Paths<'g, 'a> {
+    graph: &'g Graph<'a>,
+    policy: &'g Policy,
+    stack: Vec<(usize, usize, bool)>,
+    visits: Vec<usize>,
+    spent: usize,
+}
+
+impl<'g, 'a> Paths<'g, 'a> {
+    fn backtrack(&mut self) {
+        if let Some((cave, _, revisit)) = self.stack.pop() {
+            self.visits[cave] -= 1;
+            self.spent -= revisit as usize;
+        }
+    }
+}
+
+impl<'g, 'a> Iterator for Paths<'g, 'a> {
+    type Item = Vec<&'a str>;
+
+    fn next(&mut self) -> Option<Vec<&'a str>> {
+        let graph = self.graph;
+        while let Some(&(cave, next_index, _)) = self.stack.last() {
+            if cave == graph.end {
+                let complete = self.policy.mandatory.iter().all(|&required| self.visits[required] > 0);
+                let path = self.stack.iter().map(|(cave, _, _)| graph.names[*cave]).collect_vec();
+                self.backtrack();
+                if complete {
+                    return Some(path);
+                }
+                continue;
+            }
+            let neighbors = &graph.adjacency[cave];
+            if next_index == neighbors.len() {
+                self.backtrack();
+                continue;
+            }
+            self.stack.last_mut().unwrap().1 += 1;
+            let next = neighbors[next_index];
+            if let Some(revisit) = self.policy.visit(graph, next, self.visits[next], self.spent) {
+                self.visits[next] += 1;
+                self.spent += revisit as usize;
+                self.stack.push((next, 0, revisit));
+            }
+        }
+        None
+    }
+}
 
 fn main() {
-    let (filename, _sample_param)= if env::args().nth(1).map_or(false, |s| s == "-s") {
-        ("sample.txt", 0)
+    let args: Vec<String> = env::args().collect();
+    let filename = if args.iter().any(|s| s == "-s") {
+        "sample.txt"
     } else {
-        ("input.txt", 0)
+        "input.txt"
     };
 
     let contents = fs::read_to_string(filename)
         .unwrap_or_else(|_| panic!("Something went wrong reading the file {}", filename));
-    let paths : Vec<(&str, &str)> = contents.split('\n').map(|line| line.split('-').collect_tuple().unwrap()).collect_vec();
+    let paths : Vec<(&str, &str)> = contents.split('\n').filter(|line| !line.trim().is_empty()).map(|line| line.split('-').collect_tuple().unwrap()).collect_vec();
 
     let mut network : Network = HashMap::new();
     for path in paths {
@@ -102,7 +322,52 @@ fn main() {
     }
 
     println!("Network {:?}", network);
-    println!("Part one: {}", bfs(&network, "start", "end").unwrap());
-    println!("Part one: {}", bfs2(&network, "start", "end").unwrap());
+    let graph = Graph::new(&network, "start", "end");
+    println!("Part one: {}", graph.count_paths(&Policy::new(&graph)));
+    println!("Part two: {}", graph.count_paths(&Policy::new(&graph).with_revisits(1)));
+
+    // "--revisits K", "--limit cave=N", "--forbid cave" and "--require cave"
+    // build a custom policy, each of the last three can be repeated
+    let mut policy = Policy::new(&graph);
+    let mut custom = false;
+    for (flag, value) in args.iter().tuple_windows() {
+        custom |= ["--revisits", "--limit", "--forbid", "--require"].contains(&flag.as_str());
+        policy = match flag.as_str() {
+            "--revisits" => policy.with_revisits(value.parse().expect("--revisits takes a number")),
+            "--limit" => {
+                let (cave, limit) = value.split_once('=').expect("--limit takes cave=N");
+                policy.with_limit(graph.id(cave), limit.parse().expect("--limit takes cave=N"))
+            },
+            "--forbid" => policy.forbid(graph.id(value)),
+            "--require" => policy.require(graph.id(value)),
+            _ => policy,
+        };
+    }
+    if custom {
+        println!("Custom policy {:?}: {}", policy, graph.count_paths(&policy));
+    }
+
+    // "--dot file" writes the network for Graphviz, "--highlight a,b,c" marks a
+    // path on it and "--heatmap" shows how busy each edge is under the policy
+    if let Some(path) = args.iter().position(|s| s == "--dot").and_then(|i| args.get(i + 1)) {
+        let highlight = args.iter().position(|s| s == "--highlight").and_then(|i| args.get(i + 1))
+            .map_or(vec![], |caves| caves.split(',').map(|cave| graph.id(cave)).collect_vec());
+        let heatmap = if args.iter().any(|s| s == "--heatmap") {
+            Some(graph.edge_traffic(&policy))
+        } else {
+            None
+        };
+        fs::write(path, graph.to_dot(&highlight, heatmap.as_ref()))
+            .unwrap_or_else(|_| panic!("Could not write {}", path));
+        println!("Wrote {}", path);
+    }
+
+    // Lists paths for the custom policy lazily, "--list N" stops after N of them
+    if let Some(i) = args.iter().position(|s| s == "--list") {
+        let limit = args.get(i + 1).and_then(|n| n.parse().ok()).unwrap_or(usize::MAX);
+        for path in graph.paths(&policy).take(limit) {
+            println!("{}", path.join(","));
+        }
+    }
 
 }
\ No newline at end of file
@@ -1,9 +1,161 @@
 use itertools::Itertools;
-use std::{env, fs};
+use std::{env, fs, process};
 use std::collections::HashSet;
 
+#[derive(Debug, Clone, Copy)]
+enum Fold {
+    // Fold the right half over to the left along this column
+    Left(usize),
+    // Fold the bottom half up along this row
+    Up(usize),
+}
+
+impl Fold {
+    // Takes "fold along x=5", or just "x=5"
+    fn parse(line: &str) -> Fold {
+        let instruction = line.split_whitespace().last()
+            .unwrap_or_else(|| panic!("Empty fold instruction"));
+        let (axis, along) = instruction.split_once('=')
+            .unwrap_or_else(|| panic!("Expected axis=line in {:?}", line));
+        let along = along.parse().unwrap_or_else(|_| panic!("Bad fold line in {:?}", line));
+        match axis {
+            "x" => Fold::Left(along),
+            "y" => Fold::Up(along),
+            _ => panic!("Unknown fold axis in {:?}", line),
+        }
+    }
+}
+
+// Dots on a sheet that is always exactly as big as it needs to be
+struct Sheet {
+    dots: HashSet<(usize, usize)>,
+    width: usize,
+    height: usize,
+}
+
+impl Sheet {
+    fn new(dots: HashSet<(usize, usize)>) -> Sheet {
+        let width = dots.iter().map(|(x, _)| x + 1).max().unwrap_or(0);
+        let height = dots.iter().map(|(_, y)| y + 1).max().unwrap_or(0);
+        Sheet { dots, width, height }
+    }
+
+    // Where `position` ends up folding a sheet `size` long along `along`. If
+    // the folded over part is the longer one it hangs past the start, so
+    // everything shifts along to keep coordinates from going negative.
+    fn fold_coordinate(position: usize, size: usize, along: usize) -> usize {
+        let overhang = (size.saturating_sub(along + 1)).saturating_sub(along);
+        if position < along {
+            position + overhang
+        } else {
+            // overhang covers anything past 2 * along, so this can't underflow
+            (2 * along + overhang) - position
+        }
+    }
+
+    fn fold(&mut self, fold: Fold) {
+        let (width, height) = (self.width, self.height);
+        self.dots = self.dots.iter().map(|&(x, y)| match fold {
+            Fold::Left(along) => (Sheet::fold_coordinate(x, width, along), y),
+            Fold::Up(along) => (x, Sheet::fold_coordinate(y, height, along)),
+        }).collect();
+        match fold {
+            Fold::Left(along) => self.width = along.max(width.saturating_sub(along + 1)),
+            Fold::Up(along) => self.height = along.max(height.saturating_sub(along + 1)),
+        }
+        // A dot on the fold line stays on the crease, just past the smaller half
+        self.width = self.width.max(self.dots.iter().map(|(x, _)| x + 1).max().unwrap_or(0));
+        self.height = self.height.max(self.dots.iter().map(|(_, y)| y + 1).max().unwrap_or(0));
+    }
+
+    fn render(&self) -> String {
+        (0..self.height).map(|y| {
+            (0..self.width).map(|x| if self.dots.contains(&(x, y)) { '*' } else { '.' }).collect::<String>()
+        }).join("\n")
+    }
+}
+
+// Block letters as they come out of the puzzles: 4x6 with one blank column
+// between letters, and 6x10 with two
+const SMALL_FONT: [(char, [&str; 6]); 18] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Y', ["#...", "#...", ".#.#", "..#.", "..#.", "..#."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+const LARGE_FONT: [(char, [&str; 10]); 15] = [
+    ('A', ["..##..", ".#..#.", "#....#", "#....#", "#....#", "######", "#....#", "#....#", "#....#", "#....#"]),
+    ('B', ["#####.", "#....#", "#....#", "#....#", "#####.", "#....#", "#....#", "#....#", "#....#", "#####."]),
+    ('C', [".####.", "#....#", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#....#", ".####."]),
+    ('E', ["######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....", "#.....", "######"]),
+    ('F', ["######", "#.....", "#.....", "#.....", "#####.", "#.....", "#.....", "#.....", "#.....", "#....."]),
+    ('G', [".####.", "#....#", "#.....", "#.....", "#.....", "#..###", "#....#", "#....#", "#...##", ".###.#"]),
+    ('H', ["#....#", "#....#", "#....#", "#....#", "######", "#....#", "#....#", "#....#", "#....#", "#....#"]),
+    ('J', ["...###", "....#.", "....#.", "....#.", "....#.", "....#.", "....#.", "#...#.", "#...#.", ".###.."]),
+    ('K', ["#....#", "#...#.", "#..#..", "#.#...", "##....", "##....", "#.#...", "#..#..", "#...#.", "#....#"]),
+    ('L', ["#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "#.....", "######"]),
+    ('N', ["#....#", "##...#", "##...#", "#.#..#", "#.#..#", "#..#.#", "#..#.#", "#...##", "#...##", "#....#"]),
+    ('P', ["#####.", "#....#", "#....#", "#....#", "#####.", "#.....", "#.....", "#.....", "#.....", "#....."]),
+    ('R', ["#####.", "#....#", "#....#", "#....#", "#####.", "#..#..", "#...#.", "#...#.", "#....#", "#....#"]),
+    ('X', ["#....#", "#....#", ".#..#.", ".#..#.", "..##..", "..##..", ".#..#.", ".#..#.", "#....#", "#....#"]),
+    ('Z', ["######", ".....#", ".....#", "....#.", "...#..", "..#...", ".#....", "#.....", "#.....", "######"]),
+];
+
+#[derive(Debug)]
+struct Reading {
+    // Unrecognized glyphs come out as '?'
+    text: String,
+    // Position and drawing of every glyph that didn't match the font
+    unrecognized: Vec<(usize, String)>,
+}
+
+// Reads the folded sheet as block letters, picking the font by the height of
+// the rows that have dots. Letters are read at a fixed stride from the left
+// edge, since some (I, J) start with a blank column.
+fn recognize(sheet: &Sheet) -> Option<Reading> {
+    let top = sheet.dots.iter().map(|(_, y)| *y).min()?;
+    let bottom = sheet.dots.iter().map(|(_, y)| *y).max()?;
+    let font: Vec<(char, Vec<&str>)> = match bottom - top + 1 {
+        6 => SMALL_FONT.iter().map(|(letter, rows)| (*letter, rows.to_vec())).collect(),
+        10 => LARGE_FONT.iter().map(|(letter, rows)| (*letter, rows.to_vec())).collect(),
+        _ => return None,
+    };
+    let (width, height) = (font[0].1[0].len(), font[0].1.len());
+    let stride = if height == 6 { width + 1 } else { width + 2 };
+
+    let mut reading = Reading { text: String::new(), unrecognized: vec![] };
+    for (position, left) in (0..sheet.width).step_by(stride).enumerate() {
+        let glyph = (top..top + height).map(|y| {
+            (left..left + width).map(|x| if sheet.dots.contains(&(x, y)) { '#' } else { '.' }).collect::<String>()
+        }).collect_vec();
+        match font.iter().find(|(_, rows)| *rows == glyph) {
+            Some((letter, _)) => reading.text.push(*letter),
+            None => {
+                reading.text.push('?');
+                reading.unrecognized.push((position, glyph.join("\n")));
+            },
+        }
+    }
+    Some(reading)
+}
+
 fn main() {
-    let (filename, _sample_param) = if env::args().nth(1).map_or(false, |s| s == "-s") {
+    let (filename, _sample_param) = if env::args().nth(1).is_some_and(|s| s == "-s") {
         ("sample.txt", 0)
     } else {
         ("input.txt", 0)
@@ -11,9 +163,9 @@ fn main() {
 
     let contents = fs::read_to_string(filename)
         .unwrap_or_else(|_| panic!("Something went wrong reading the file {}", filename));
-    let input_lines = contents.split('\n');
-    let mut pts: HashSet<(usize, usize)> = input_lines.clone()
-        .take_while(|line| *line != "")
+    let input_lines = contents.split('\n').map(|line| line.trim());
+    let pts: HashSet<(usize, usize)> = input_lines.clone()
+        .take_while(|line| !line.is_empty())
         .map(|line| {
             line.split(',')
                 .map(|num| num.parse().unwrap())
@@ -22,35 +174,35 @@ fn main() {
         })
         .collect();
     // println!("{} pts, {:?}", pts.len(), pts);
-    let folds : Vec<(bool, usize)> = input_lines.skip_while(|line| *line != "").skip(1).map(|line| {
-        let (fold, num) = line.split('=').collect_tuple().unwrap();
-
-        (fold.chars().nth(11).unwrap() == 'x', num.parse().unwrap()) 
-    }).collect_vec();
+    let folds: Vec<Fold> = input_lines
+        .skip_while(|line| !line.is_empty())
+        .filter(|line| !line.is_empty())
+        .map(Fold::parse)
+        .collect_vec();
     // println!("{:?}", folds);
 
-    for (is_vertical, along_line) in folds {
-        println!("Folding {} along {}", if is_vertical {"vertically"} else {"horizontally"}, along_line);
-        let folded : HashSet<(usize, usize)> = pts.iter().map(|(x, y)| {
-            if is_vertical && x >= &along_line {
-                (2* along_line - x, *y)
-            } else if !is_vertical && y >= &along_line {
-                (*x, 2* along_line - y)
-            } else {
-                (*x, *y)
-            }
-        }).collect();
-        pts = folded;
-        // println!("Folded pts length = {}, pts = {:?}", pts.len(), pts);
+    let mut sheet = Sheet::new(pts);
+    println!("{} dots on a {}x{} sheet", sheet.dots.len(), sheet.width, sheet.height);
+    for fold in folds {
+        sheet.fold(fold);
+        println!("After {:?}: {} dots visible on a {}x{} sheet", fold, sheet.dots.len(), sheet.width, sheet.height);
     }
 
-    let mut arr = [['.'; 50];50];
+    println!("{}", sheet.render());
 
-    for (x, y) in pts {
-        arr[y][x] = '*';
+    match recognize(&sheet) {
+        Some(reading) => {
+            println!("Part two: {}", reading.text);
+            for (position, glyph) in &reading.unrecognized {
+                println!("Unrecognized glyph at position {}:\n{}", position, glyph);
+            }
+            if !reading.unrecognized.is_empty() {
+                process::exit(1);
+            }
+        },
+        None => {
+            println!("Folded sheet isn't 6 or 10 rows of letters");
+            process::exit(1);
+        },
     }
-    for row in arr {
-        println!("{:?}", row);
-    } 
-
 }
@@ -5,14 +5,54 @@ fn main() {
     let contents = fs::read_to_string(filename)
         .expect("Something went wrong reading the file");
 
-    let lines = contents.split("\n");
-    let (g1, e1) = part_two(lines);
+    let report = Report::new(contents.split('\n'));
+    let (gamma, epsilon) = part_one(&report);
+    println!("Power: ({:b}, {:b}), ({}, {}), mult is {}", gamma, epsilon, gamma, epsilon, gamma * epsilon);
+    let (g1, e1) = part_two(&report);
     println!("Output is ({:b}, {:b}), ({}, {}), mult is {}", g1, e1, g1, e1, g1*e1);
 }
 
-fn fold_bit_counts(bit_counts: &Vec<(i32,i32)>, gamma: bool) -> i32{
-    bit_counts.iter().fold(0, |mut acc, (zeroes, ones)| { 
-        acc = acc << 1;
+// Diagnostic lines packed into integers, plus a transposed copy where each
+// column is a bitset over the lines so tallies are just popcounts.
+struct Report {
+    values: Vec<u64>,
+    width: usize,
+    columns: Vec<Vec<u64>>,
+}
+
+impl Report {
+    fn new<'a, I>(lines: I) -> Report where I: Iterator<Item = &'a str> {
+        let mut width = 0;
+        let values: Vec<u64> = lines.filter(|line| !line.is_empty()).map(|line| {
+            width = width.max(line.len());
+            u64::from_str_radix(line, 2).unwrap()
+        }).collect();
+        assert!(width <= 64, "Lines longer than 64 bits are not supported");
+
+        let words = values.len().div_ceil(64);
+        let mut columns = vec![vec![0u64; words]; width];
+        for (row, value) in values.iter().enumerate() {
+            for (bit, column) in columns.iter_mut().enumerate() {
+                if value >> (width - 1 - bit) & 1 == 1 {
+                    column[row / 64] |= 1 << (row % 64);
+                }
+            }
+        }
+        Report { values, width, columns }
+    }
+
+    // (zeroes, ones) for every column, most significant first
+    fn bit_counts(&self) -> Vec<(usize, usize)> {
+        self.columns.iter().map(|column| {
+            let ones = column.iter().map(|word| word.count_ones() as usize).sum::<usize>();
+            (self.values.len() - ones, ones)
+        }).collect()
+    }
+}
+
+fn fold_bit_counts(bit_counts: &[(usize, usize)], gamma: bool) -> u64 {
+    bit_counts.iter().fold(0, |acc, (zeroes, ones)| {
+        let acc = acc << 1;
         if zeroes > ones {
             acc | (if gamma {0} else {1})
         } else {
@@ -20,52 +60,51 @@ fn fold_bit_counts(bit_counts: &Vec<(i32,i32)>, gamma: bool) -> i32{
         }
     })
 }
-fn get_bit_counts<'a, I>(lines: I) -> Vec<(i32, i32)> where I: Iterator<Item = &'a str> {
-    lines.fold(vec![(0,0); 12], |mut acc, val| {
-        val.chars().enumerate().for_each(|(i, c)| {
-            match c {
-                '0' => acc[i].0 += 1,
-                '1' => acc[i].1 += 1,
-                _ => panic!()
-            }
-        });
-        acc
-    })
-}
-fn part_one<'a, I>(lines: I) -> (i32, i32) where I: Iterator<Item = &'a str> {
-    let bit_counts = get_bit_counts(lines);
+
+fn part_one(report: &Report) -> (u64, u64) {
+    let bit_counts = report.bit_counts();
     println!("bit counts: {:?}", bit_counts);
-    let gamma : i32 = fold_bit_counts(&bit_counts, true);
-    let epsilon: i32 = fold_bit_counts(&bit_counts, false);
+    let gamma = fold_bit_counts(&bit_counts, true);
+    let epsilon = fold_bit_counts(&bit_counts, false);
     (gamma, epsilon)
 }
 
-
-fn filter<'a>(index: usize, lines: Vec<&'a str>, greater: bool) -> Vec<&'a str> {
-    let bit_counts = get_bit_counts(lines.clone().into_iter());
-    let vals = lines.into_iter().filter(|s| {
-        let (zeros, ones) = bit_counts[index];
-        let char = s.chars().nth(index).unwrap();
-        if greater {
-            zeros > ones && char == '0' || ones >= zeros && char == '1'
-        } else {
-            ones >= zeros && char == '0' || zeros > ones && char == '1'
+// Moves every value with `bit` set to the front, returning how many there are
+fn partition(values: &mut [u64], bit: usize) -> usize {
+    let mut ones = 0;
+    for i in 0..values.len() {
+        if values[i] >> bit & 1 == 1 {
+            values.swap(i, ones);
+            ones += 1;
         }
-    }).collect();
-    vals
+    }
+    ones
 }
 
-fn part_two<'a, I>(lines: I) -> (i32, i32) where I: Iterator<Item = &'a str> {
-    let mut greater : Vec<&str>= lines.collect();
-    let mut lesser = greater.clone();
-    for x in 0..12 {
-        if greater.len() > 1 {
-            greater = filter(x, greater, true);
+fn rating(values: &mut [u64], width: usize, greater: bool) -> u64 {
+    let mut remaining = values;
+    for bit in (0..width).rev() {
+        if remaining.len() <= 1 {
+            break;
         }
-        if lesser.len() > 1 {
-            lesser = filter(x, lesser, false);
+        let ones = partition(remaining, bit);
+        let zeroes = remaining.len() - ones;
+        if ones == 0 || zeroes == 0 {
+            continue;
         }
+        let keep_ones = if greater { ones >= zeroes } else { ones < zeroes };
+        remaining = if keep_ones {
+            &mut remaining[..ones]
+        } else {
+            &mut remaining[ones..]
+        };
     }
-    (i32::from_str_radix(greater[0], 2).unwrap(), i32::from_str_radix(lesser[0], 2).unwrap())
+    remaining[0]
+}
 
-}
\ No newline at end of file
+fn part_two(report: &Report) -> (u64, u64) {
+    let mut values = report.values.clone();
+    let oxygen = rating(&mut values, report.width, true);
+    let co2 = rating(&mut values, report.width, false);
+    (oxygen, co2)
+}
@@ -1,109 +1,317 @@
-use std::fs;
+use std::{collections::{HashMap, HashSet}, env, fmt, fs, process};
 use itertools::Itertools;
 
 fn main() {
-    let filename = "input.txt";
+    let args: Vec<String> = env::args().collect();
+    let filename = if args.iter().any(|s| s == "-s") {
+        "sample.txt"
+    } else {
+        "input.txt"
+    };
+    let diagonals = args.iter().any(|s| s == "-d");
+
     let contents = fs::read_to_string(filename)
-        .expect("Something went wrong reading the file");
-
-    let mut lines = contents.split("\n");
-
-    let nums : Vec<i32> = lines.next().unwrap().split(",").map(|s| s.parse().unwrap()).collect();
-    let mut bingos :Vec<Bingo> = lines
-        .chunks(6).into_iter()
-        .map(|chunk| {
-            chunk.map(|s| { 
-                // println!("s: {:?}", s);
-                s.split_whitespace().collect() 
-            }).collect()
-        }).map(|vals| { Bingo::new(vals)}).collect();
-
-    let mut winning_indices : Vec<usize> = vec![];
-    for num in nums {
-        let mut index = 0;
-        for bingo in &mut bingos {
-            bingo.number_called(&num);
-            let answer = bingo.check();
-            if answer > 0 {
-                if !winning_indices.iter().any(|candidate| {
-                    candidate == &index 
-                }) {
-                    println!("Found answer, is {}", answer * num);
-                    winning_indices.push(index);
+        .unwrap_or_else(|_| panic!("Something went wrong reading the file {}", filename));
+
+    let (nums, boards) = match parse_input(&contents) {
+        Ok(input) => input,
+        Err(errors) => {
+            for error in &errors {
+                println!("Error: {}", error);
+            }
+            process::exit(1);
+        }
+    };
+    let mut bingos: Vec<Bingo> = boards.into_iter()
+        .map(|(line, rows)| Bingo::new(line, rows, diagonals))
+        .collect();
+
+    for warning in diagnose(&bingos, &nums) {
+        println!("Warning: {}", warning);
+    }
+
+    let wins = play(&mut bingos, &nums);
+    for win in &wins {
+        println!("Board {} won on draw #{} ({}), score is {}",
+            win.board, win.draw + 1, nums[win.draw], win.score.value());
+    }
+    match (wins.first(), wins.last()) {
+        (Some(first), Some(last)) => {
+            println!("First winner: board {}, score {}", first.board, first.score.value());
+            println!("Last winner: board {}, score {}", last.board, last.score.value());
+        },
+        _ => println!("No board won"),
+    }
+    let losers = bingos.len() - wins.len();
+    if losers > 0 {
+        println!("{} boards never won", losers);
+    }
+}
+
+// Board index and line numbers are 0- and 1-based respectively, matching the
+// rest of the output and most editors
+#[derive(Debug)]
+enum InputError {
+    NoDraws,
+    BadDraw { position: usize, value: String },
+    NoBoards,
+    BadNumber { board: usize, line: usize, value: String },
+    RowWidth { board: usize, line: usize, expected: usize, found: usize },
+    NotSquare { board: usize, line: usize, rows: usize, columns: usize },
+    DuplicateNumber { board: usize, line: usize, value: i32 },
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputError::NoDraws => write!(f, "line 1: no numbers drawn"),
+            InputError::BadDraw { position, value } =>
+                write!(f, "line 1: draw #{} {:?} is not a number", position + 1, value),
+            InputError::NoBoards => write!(f, "no boards found"),
+            InputError::BadNumber { board, line, value } =>
+                write!(f, "board {}, line {}: {:?} is not a number", board, line, value),
+            InputError::RowWidth { board, line, expected, found } =>
+                write!(f, "board {}, line {}: expected {} numbers, found {}", board, line, expected, found),
+            InputError::NotSquare { board, line, rows, columns } =>
+                write!(f, "board {}, line {}: board has {} rows of {} numbers", board, line, rows, columns),
+            InputError::DuplicateNumber { board, line, value } =>
+                write!(f, "board {}, line {}: {} appears more than once", board, line, value),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Warning {
+    NeverDrawn { board: usize, line: usize, value: i32 },
+    CannotWin { board: usize, line: usize },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::NeverDrawn { board, line, value } =>
+                write!(f, "board {}, line {}: {} is never drawn", board, line, value),
+            Warning::CannotWin { board, line } =>
+                write!(f, "board {}, line {}: board can never win", board, line),
+        }
+    }
+}
+
+// Line number of the first row, then the rows themselves
+type BoardInput = (usize, Vec<Vec<i32>>);
+
+// Returns the draws and every board, or every problem found in the input
+fn parse_input(contents: &str) -> Result<(Vec<i32>, Vec<BoardInput>), Vec<InputError>> {
+    let mut errors = Vec::new();
+    let mut lines = contents.split('\n').enumerate().map(|(i, line)| (i + 1, line));
+
+    let draw_line = lines.next().map_or("", |(_, line)| line.trim());
+    let mut nums = Vec::new();
+    if draw_line.is_empty() {
+        errors.push(InputError::NoDraws);
+    } else {
+        for (position, value) in draw_line.split(',').enumerate() {
+            match value.trim().parse() {
+                Ok(num) => nums.push(num),
+                Err(_) => errors.push(InputError::BadDraw { position, value: value.to_string() }),
+            }
+        }
+    }
+
+    let mut boards = Vec::new();
+    let groups = lines.group_by(|(_, line)| line.trim().is_empty());
+    for (board, (_, group)) in groups.into_iter().filter(|(blank, _)| !blank).enumerate() {
+        let group: Vec<(usize, &str)> = group.collect();
+        let first_line = group[0].0;
+        let mut rows: Vec<Vec<i32>> = Vec::new();
+        let mut seen = HashSet::new();
+        for (line, row) in group {
+            let mut values = Vec::new();
+            for value in row.split_whitespace() {
+                match value.parse() {
+                    Ok(num) => {
+                        if !seen.insert(num) {
+                            errors.push(InputError::DuplicateNumber { board, line, value: num });
+                        }
+                        values.push(num);
+                    },
+                    Err(_) => errors.push(InputError::BadNumber { board, line, value: value.to_string() }),
+                }
+            }
+            let found = row.split_whitespace().count();
+            if let Some(expected) = rows.first().map(|first| first.len()) {
+                if found != expected {
+                    errors.push(InputError::RowWidth { board, line, expected, found });
                 }
             }
-            index+= 1;
+            rows.push(values);
+        }
+        let columns = rows[0].len();
+        if rows.len() != columns {
+            errors.push(InputError::NotSquare { board, line: first_line, rows: rows.len(), columns });
         }
+        boards.push((first_line, rows));
     }
+    if boards.is_empty() {
+        errors.push(InputError::NoBoards);
+    }
+
+    if errors.is_empty() {
+        Ok((nums, boards))
+    } else {
+        Err(errors)
+    }
+}
+
+// Flags numbers that are never drawn and boards where no line is ever completed
+fn diagnose(bingos: &[Bingo], nums: &[i32]) -> Vec<Warning> {
+    let drawn: HashSet<i32> = nums.iter().copied().collect();
+    let mut warnings = Vec::new();
+    for (board, bingo) in bingos.iter().enumerate() {
+        let n = bingo.size;
+        let is_drawn = |(row, col): (usize, usize)| {
+            bingo.cells[row * n + col].is_none_or(|value| drawn.contains(&value))
+        };
+        for (i, value) in bingo.cells.iter().enumerate() {
+            if let Some(value) = value {
+                if !drawn.contains(value) {
+                    warnings.push(Warning::NeverDrawn { board, line: bingo.line + i / n, value: *value });
+                }
+            }
+        }
 
-    println!("No answer found");
+        let can_win = (0..n).any(|row| (0..n).all(|col| is_drawn((row, col))))
+            || (0..n).any(|col| (0..n).all(|row| is_drawn((row, col))))
+            || bingo.diagonals && (
+                (0..n).all(|i| is_drawn((i, i))) || (0..n).all(|i| is_drawn((i, n - 1 - i)))
+            );
+        if !can_win {
+            warnings.push(Warning::CannotWin { board, line: bingo.line });
+        }
+    }
+    warnings
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Score {
+    unmarked_sum: i32,
+    last_called: i32,
+}
 
+impl Score {
+    fn value(&self) -> i32 {
+        self.unmarked_sum * self.last_called
+    }
 }
 
-const DIMENSION: usize= 5;
+#[derive(Debug)]
+struct Win {
+    board: usize,
+    // Index into the draw sequence
+    draw: usize,
+    score: Score,
+}
 
-struct Bingo {
-    // TODO use fixed size arrays here
-    rows: Vec<Vec<Option<i32>>>,
+// Plays every draw against every board, returning boards in the order they won
+fn play(bingos: &mut [Bingo], nums: &[i32]) -> Vec<Win> {
+    // number -> every (board, row, col) it appears at, in board order
+    let mut index: HashMap<i32, Vec<(usize, usize, usize)>> = HashMap::new();
+    for (board, bingo) in bingos.iter().enumerate() {
+        for (i, value) in bingo.cells.iter().enumerate() {
+            if let Some(value) = value {
+                index.entry(*value).or_default().push((board, i / bingo.size, i % bingo.size));
+            }
+        }
+    }
+
+    let mut wins = Vec::new();
+    for (draw, &num) in nums.iter().enumerate() {
+        let hits = match index.get(&num) {
+            Some(hits) => hits,
+            None => continue,
+        };
+        for &(board, row, col) in hits {
+            let bingo = &mut bingos[board];
+            if bingo.won {
+                continue;
+            }
+            bingo.mark(row, col);
+            if let Some(score) = bingo.check(row, col, num) {
+                bingo.won = true;
+                wins.push(Win { board, draw, score });
+            }
+        }
+        if wins.len() == bingos.len() {
+            break;
+        }
+    }
+    wins
 }
 
+struct Bingo {
+    // Line number of the first row in the input
+    line: usize,
+    size: usize,
+    diagonals: bool,
+    // Row-major, None once the number has been called
+    cells: Vec<Option<i32>>,
+    row_hits: Vec<usize>,
+    col_hits: Vec<usize>,
+    // Main diagonal, then anti-diagonal
+    diagonal_hits: [usize; 2],
+    unmarked_sum: i32,
+    won: bool,
+}
 
 impl Bingo {
-    fn new(input: Vec<Vec<&str>>) -> Bingo {  
+    fn new(line: usize, rows: Vec<Vec<i32>>, diagonals: bool) -> Bingo {
+        let size = rows.len();
+        assert!(rows.iter().all(|row| row.len() == size), "Board is not square: {:?}", rows);
+        let cells: Vec<Option<i32>> = Iterator::flatten(rows.into_iter()).map(Some).collect();
         Bingo {
-            rows: input.iter().skip(1).map(|row| {
-                row.iter().map(|value| {
-                    Some(value.parse().unwrap())
-                }).collect()
-            }).collect()
+            line,
+            size,
+            diagonals,
+            unmarked_sum: Iterator::flatten(cells.iter()).sum(),
+            cells,
+            row_hits: vec![0; size],
+            col_hits: vec![0; size],
+            diagonal_hits: [0; 2],
+            won: false,
         }
     }
 
-    fn number_called(self: &mut Self, called: &i32) {
-        // self.rows = self.rows.iter().map(|row| {
-        //     row.into_iter().map(|value| {
-        //         match value {
-        //             Some(actual) => if actual == called {None} else {value.as_ref()}
-        //             None => value.as_ref()
-        //         }
-        //     }).collect()
-        // }).collect();
-        for row in &mut self.rows {
-            for value in row {
-                match value {
-                    Some(actual) => if actual == called { *value = None},
-                    None => ()
-                }
+    fn mark(&mut self, row: usize, col: usize) {
+        let n = self.size;
+        if let Some(value) = self.cells[row * n + col].take() {
+            self.unmarked_sum -= value;
+            self.row_hits[row] += 1;
+            self.col_hits[col] += 1;
+            if row == col {
+                self.diagonal_hits[0] += 1;
+            }
+            if row + col == n - 1 {
+                self.diagonal_hits[1] += 1;
             }
         }
     }
 
-    // Returns 0 if the card is not a winner, otherwise sums up everything else
-    fn check(self: &Self) -> i32 {
-        let any_rows_filled = self.rows.iter().any(|row| {
-            row.iter().all(|value| value.is_none())
-        });
+    // Returns None if marking (row, col) didn't complete a line through it,
+    // otherwise sums up everything else. Only those lines can have changed.
+    fn check(&self, row: usize, col: usize, last_called: i32) -> Option<Score> {
+        let n = self.size;
+        let any_rows_filled = self.row_hits[row] == n;
+        let any_cols_filled = self.col_hits[col] == n;
+        let any_diagonals_filled = self.diagonals
+            && ((row == col && self.diagonal_hits[0] == n) || (row + col == n - 1 && self.diagonal_hits[1] == n));
 
-        let any_cols_filled = self.rows.iter().fold(vec![0; DIMENSION], |mut acc, row| {
-            row.iter().enumerate().for_each(|(i, value)| {
-                if value.is_none() {
-                    acc[i] += 1;
-                }
-            });
-            acc
-        }).iter().any(|column_none_count| {
-            column_none_count == &DIMENSION
-        });
-        if any_cols_filled || any_rows_filled {
-            Iterator::flatten(self.rows.iter()).fold(0, |acc, val| {
-                match val {
-                    Some(value) => acc + value,
-                    None => acc
-                }
+        if any_rows_filled || any_cols_filled || any_diagonals_filled {
+            Some(Score {
+                unmarked_sum: self.unmarked_sum,
+                last_called,
             })
         } else {
-            0
+            None
         }
-
     }
 }
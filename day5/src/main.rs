@@ -1,84 +1,271 @@
-use std::fs;
+use std::{collections::{BTreeSet, HashMap}, env, fs};
 // use itertools::Itertools;
 
-type Point = (usize,usize);
+type Point = (i64, i64);
 type Segment = (Point, Point);
-const DIMENSION: usize = 1000;
-const filename: &str = "input.txt";
+// Only points covered at least once are stored, so coordinates can be anything
+type Board = HashMap<Point, usize>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Raster {
+    // Horizontal, vertical and 45 degree lines only, anything else is skipped
+    Axis,
+    // Every grid point the line passes closest to
+    Bresenham,
+    // Only points the line passes exactly through
+    Lattice,
+}
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    let filename = if args.iter().any(|s| s == "-s") {
+        "sample.txt"
+    } else {
+        "input.txt"
+    };
+    let min_overlaps: usize = args.iter()
+        .position(|s| s == "-k")
+        .and_then(|i| args.get(i + 1))
+        .map_or(2, |k| k.parse().expect("-k takes a number"));
+    let raster = match args.iter().position(|s| s == "-m").and_then(|i| args.get(i + 1)) {
+        None => Raster::Axis,
+        Some(mode) => match mode.as_str() {
+            "axis" => Raster::Axis,
+            "bresenham" => Raster::Bresenham,
+            "lattice" => Raster::Lattice,
+            _ => panic!("Unknown raster mode {}, expected axis, bresenham or lattice", mode),
+        },
+    };
+
     let contents = fs::read_to_string(filename)
-        .expect("Something went wrong reading the file");
+        .unwrap_or_else(|_| panic!("Something went wrong reading the file {}", filename));
 
-    let input_lines = contents.split("\n");
-    let segments : Vec<Segment>= input_lines.map(|s| { 
+    let input_lines = contents.split('\n').filter(|s| !s.trim().is_empty());
+    let segments: Vec<Segment> = input_lines.map(|s| {
         let pts: Vec<Point> = s.split(" -> ").map(|point_str| {
-            let xy: Vec<usize> = point_str.split(",").map(|i| i.parse().unwrap()).collect();
+            let xy: Vec<i64> = point_str.split(',').map(|i| i.trim().parse().unwrap()).collect();
             (xy[0], xy[1])
         }).collect();
         (pts[0], pts[1])
     }).collect();
     println!("segments: {:?}", segments);
 
-    let mut board = [[0; DIMENSION]; DIMENSION]; // 2d array with 1m elements
+    if args.iter().any(|s| s == "--sweep") {
+        // The sweep only finds points where segments meet
+        assert!(min_overlaps >= 2, "--sweep needs -k of at least 2, got {}", min_overlaps);
+        let hotspots = sweep(&segments, raster);
+        if args.iter().any(|s| s == "--hotspots") {
+            let mut points: Vec<&Point> = hotspots.keys().filter(|p| hotspots[p].len() >= min_overlaps).collect();
+            points.sort();
+            for point in points {
+                let overlapping = &hotspots[point];
+                println!("{:?}: segments {:?}", point, overlapping);
+            }
+        }
+        let count = hotspots.values().filter(|o| o.len() >= min_overlaps).count();
+        println!("sweep score (>= {} lines): {:?}", min_overlaps, count);
+        return;
+    }
+
+    let mut board = Board::new();
     for segment in segments {
-        apply_segment(&segment, &mut board);
+        apply_segment(&segment, &mut board, raster);
     }
     println!("\n");
-    // print_board(&board);
-    println!("score: {:?}", score(&board));
+    if filename == "sample.txt" {
+        print_board(&board);
+    }
+    println!("score (>= {} lines): {:?}", min_overlaps, score(&board, min_overlaps));
+}
+
+fn apply_segment(segment: &Segment, board: &mut Board, raster: Raster) {
+    let points = match raster {
+        Raster::Axis => axis_points(segment),
+        Raster::Bresenham => bresenham_points(segment),
+        Raster::Lattice => lattice_points(segment),
+    };
+    for point in points {
+        *board.entry(point).or_insert(0) += 1;
+    }
 }
 
-fn apply_segment(segment: &Segment, board: &mut [[i32; DIMENSION]; DIMENSION]) {
-    let ((x1,y1),(x2,y2)) = segment;
-    let (y_1, y_2) = if y2 > y1 {(y1, y2)} else {(y2, y1)};
-    let (x_1, x_2) = if x2 > x1 {(x1, x2)} else {(x2, x1)};
-    if x1 == x2 { // vertical
+fn axis_points(segment: &Segment) -> Vec<Point> {
+    let ((x1, y1), (x2, y2)) = *segment;
+    let (dx, dy) = (x2 - x1, y2 - y1);
+    if dx == 0 {
         println!("Drawing vertical {:?}", segment);
-        for y in *y_1..=*y_2 {
-            board[y][*x1] += 1;
-        }
-    } else if y1 == y2 { //horizontal
+    } else if dy == 0 {
         println!("Drawing horizontal {:?}", segment);
-        for x in *x_1..=*x_2 {
-            board[*y1][x] += 1;
-        }
-    } else if (y_2 - y_1) == (x_2 - x_1) { // 45 degree diagonal
+    } else if dx.abs() == dy.abs() {
         println!("Drawing diagonal {:?}", segment);
-        let length = y_2 - y_1;
-        if(x2 > x1) {
-            if (y2 > y1) {
-                for i in 0..=length {
-                    board[*y1 + i][*x1 + i] += 1;
-                }
-            } else {
-                for i in 0..=length {
-                    board[*y1 - i][*x1 + i] += 1;
-                }
+    } else { //non 45 degree diagonal
+        println!("Ignoring diagonal {:?}", segment);
+        return vec![];
+    }
+    let length = dx.abs().max(dy.abs());
+    let (step_x, step_y) = (dx.signum(), dy.signum());
+    (0..=length).map(|i| (x1 + i * step_x, y1 + i * step_y)).collect()
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 { a.abs() } else { gcd(b, a % b) }
+}
+
+fn lattice_points(segment: &Segment) -> Vec<Point> {
+    let ((x1, y1), (x2, y2)) = *segment;
+    let (dx, dy) = (x2 - x1, y2 - y1);
+    let steps = gcd(dx, dy);
+    if steps == 0 {
+        return vec![(x1, y1)];
+    }
+    let (step_x, step_y) = (dx / steps, dy / steps);
+    (0..=steps).map(|i| (x1 + i * step_x, y1 + i * step_y)).collect()
+}
+
+// Integer Bresenham, works in every octant
+fn bresenham_points(segment: &Segment) -> Vec<Point> {
+    let ((x1, y1), (x2, y2)) = *segment;
+    let (dx, dy) = ((x2 - x1).abs(), -(y2 - y1).abs());
+    let (step_x, step_y) = ((x2 - x1).signum(), (y2 - y1).signum());
+    let mut error = dx + dy;
+    let (mut x, mut y) = (x1, y1);
+    let mut points = Vec::with_capacity((dx.max(-dy) + 1) as usize);
+    loop {
+        points.push((x, y));
+        if (x, y) == (x2, y2) {
+            break;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+    points
+}
+
+fn cross(a: Point, b: Point) -> i128 {
+    a.0 as i128 * b.1 as i128 - a.1 as i128 * b.0 as i128
+}
+
+fn dot(a: Point, b: Point) -> i128 {
+    a.0 as i128 * b.0 as i128 + a.1 as i128 * b.1 as i128
+}
+
+fn sub(a: Point, b: Point) -> Point {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+fn on_segment(segment: &Segment, point: Point) -> bool {
+    let (p, q) = *segment;
+    cross(sub(q, p), sub(point, p)) == 0
+        && p.0.min(q.0) <= point.0 && point.0 <= p.0.max(q.0)
+        && p.1.min(q.1) <= point.1 && point.1 <= p.1.max(q.1)
+}
+
+// Lattice points shared by two segments. Crossing segments share at most one,
+// collinear ones share every lattice point of their overlap.
+fn shared_points(a: &Segment, b: &Segment) -> Vec<Point> {
+    let (p, r) = (a.0, sub(a.1, a.0));
+    let (q, s) = (b.0, sub(b.1, b.0));
+    if r == (0, 0) {
+        return if on_segment(b, p) { vec![p] } else { vec![] };
+    }
+    if s == (0, 0) {
+        return if on_segment(a, q) { vec![q] } else { vec![] };
+    }
+
+    let denominator = cross(r, s);
+    if denominator == 0 {
+        if cross(sub(q, p), r) != 0 {
+            return vec![]; // parallel
+        }
+        // Collinear: walk a's lattice points that fall within b
+        let steps = gcd(r.0, r.1);
+        let step = (r.0 / steps, r.1 / steps);
+        let length = dot(step, step);
+        let start = dot(sub(b.0, p), step) / length;
+        let end = dot(sub(b.1, p), step) / length;
+        let (low, high) = (start.min(end).max(0), start.max(end).min(steps as i128));
+        return (low..=high)
+            .map(|k| (p.0 + k as i64 * step.0, p.1 + k as i64 * step.1))
+            .collect();
+    }
+
+    // p + t*r == q + u*s with t, u in [0, 1], kept as fractions over denominator
+    let (t, u) = (cross(sub(q, p), s), cross(sub(q, p), r));
+    let (t, u, denominator) = if denominator < 0 {
+        (-t, -u, -denominator)
+    } else {
+        (t, u, denominator)
+    };
+    if t < 0 || t > denominator || u < 0 || u > denominator {
+        return vec![];
+    }
+    let x = p.0 as i128 * denominator + r.0 as i128 * t;
+    let y = p.1 as i128 * denominator + r.1 as i128 * t;
+    if x % denominator != 0 || y % denominator != 0 {
+        return vec![]; // crosses between grid points
+    }
+    vec![((x / denominator) as i64, (y / denominator) as i64)]
+}
+
+// Finds every point covered by two or more segments without rasterizing them.
+// Segments are swept left to right; each is only intersected against the ones
+// whose x extent is still active, after a cheap bounding box check.
+fn sweep(segments: &[Segment], raster: Raster) -> HashMap<Point, BTreeSet<usize>> {
+    let included = |segment: &Segment| {
+        let (dx, dy) = sub(segment.1, segment.0);
+        match raster {
+            Raster::Axis => dx == 0 || dy == 0 || dx.abs() == dy.abs(),
+            Raster::Lattice => true,
+            Raster::Bresenham => panic!("The sweep only supports axis and lattice modes"),
+        }
+    };
+    let mut order: Vec<usize> = (0..segments.len()).filter(|&i| included(&segments[i])).collect();
+    let min_x = |i: usize| segments[i].0.0.min(segments[i].1.0);
+    let max_x = |i: usize| segments[i].0.0.max(segments[i].1.0);
+    order.sort_by_key(|&i| min_x(i));
+
+    let mut hotspots: HashMap<Point, BTreeSet<usize>> = HashMap::new();
+    let mut active: Vec<usize> = Vec::new();
+    for &current in &order {
+        active.retain(|&other| max_x(other) >= min_x(current));
+        let (c0, c1) = segments[current];
+        for &other in &active {
+            let (o0, o1) = segments[other];
+            if c0.1.max(c1.1) < o0.1.min(o1.1) || o0.1.max(o1.1) < c0.1.min(c1.1) {
+                continue;
             }
-        } else {
-            if (y2 > y1) {
-                for i in 0..=length {
-                    board[*y1 + i][*x1 - i] += 1;
-                }
-            } else {
-                for i in 0..=length {
-                    board[*y1 - i][*x1 - i] += 1;
-                }
+            for point in shared_points(&segments[current], &segments[other]) {
+                let overlapping = hotspots.entry(point).or_default();
+                overlapping.insert(current);
+                overlapping.insert(other);
             }
-
         }
-    } else { //non 45 degree diagonal
-        println!("Ignoring diagonal {:?}", segment);
+        active.push(current);
     }
-    // print_board(&board);
+    hotspots
 }
 
-fn score(board: &[[i32; DIMENSION]; DIMENSION]) -> usize {
-    board.iter().flatten().filter(|v| **v >= 2).count()
+// Number of points covered by at least `min_overlaps` lines
+fn score(board: &Board, min_overlaps: usize) -> usize {
+    board.values().filter(|v| **v >= min_overlaps).count()
 }
-fn print_board(board: &[[i32; DIMENSION]; DIMENSION]) {
-    board.iter().enumerate().for_each(|(i,row)| {
-        println!("{} {:?}", i, row);
-    });
+
+fn print_board(board: &Board) {
+    let min_x = board.keys().map(|(x, _)| *x).min().unwrap_or(0);
+    let max_x = board.keys().map(|(x, _)| *x).max().unwrap_or(0);
+    let min_y = board.keys().map(|(_, y)| *y).min().unwrap_or(0);
+    let max_y = board.keys().map(|(_, y)| *y).max().unwrap_or(0);
+    for y in min_y..=max_y {
+        let row: String = (min_x..=max_x).map(|x| {
+            board.get(&(x, y)).map_or('.', |count| std::char::from_digit((*count).min(9) as u32, 10).unwrap())
+        }).collect();
+        println!("{} {}", y, row);
+    }
 }
@@ -1,46 +1,285 @@
-use std::fs;
+use std::{env, fs};
+use num::{BigUint, One, Zero};
 
 const FILENAME : &str = "input.txt";
 
-fn main() {
-    let contents = fs::read_to_string(FILENAME)
-        .expect("Something went wrong reading the file");
+type Matrix = Vec<Vec<BigUint>>;
+
+// How one kind of fish ages and reproduces. Lanternfish are reset 6, newborn 8,
+// no maturation delay and immortal.
+#[derive(Debug, Clone)]
+struct Species {
+    name: String,
+    // Timer a fish restarts at after spawning
+    reset: usize,
+    // Timer a newly spawned fish starts at
+    newborn: usize,
+    // Age in days before a fish can spawn, an immature fish whose timer runs
+    // out just restarts it
+    maturation: usize,
+    // Age in days at which a fish dies
+    lifespan: Option<usize>,
+}
+
+impl Species {
+    fn lanternfish() -> Species {
+        Species { name: "lanternfish".to_string(), reset: 6, newborn: 8, maturation: 0, lifespan: None }
+    }
+
+    // Parses "name key=value ...", any key left out keeps the lanternfish value
+    fn from_header(header: &str) -> Species {
+        let mut words = header.split_whitespace();
+        let mut species = Species::lanternfish();
+        species.name = words.next().expect("Species needs a name").to_string();
+        for word in words {
+            let (key, value) = word.split_once('=')
+                .unwrap_or_else(|| panic!("Expected key=value, found {}", word));
+            let parse = |value: &str| -> usize {
+                value.parse().unwrap_or_else(|_| panic!("Bad value for {}: {}", key, value))
+            };
+            match key {
+                "reset" => species.reset = parse(value),
+                "newborn" => species.newborn = parse(value),
+                "maturation" => species.maturation = parse(value),
+                "lifespan" => species.lifespan = if value == "none" { None } else { Some(parse(value)) },
+                _ => panic!("Unknown species setting {}", key),
+            }
+        }
+        species
+    }
 
-    let mut input_lines = contents.split("\n");
-    let mut fish: Vec<usize> = input_lines.next().unwrap().split(",").map(|s| s.parse().unwrap()).collect();
-    let mut counts = [0; 9];
-    for f in &fish {
-        counts[*f] += 1;
+    fn max_timer(&self) -> usize {
+        self.reset.max(self.newborn)
     }
+
+    // Ages past this all behave the same, so they share a state
+    fn max_age(&self) -> usize {
+        self.maturation.max(self.lifespan.unwrap_or(0))
+    }
+
+    fn state_count(&self) -> usize {
+        (self.max_age() + 1) * (self.max_timer() + 1)
+    }
+
+    fn state(&self, timer: usize, age: usize) -> usize {
+        age.min(self.max_age()) * (self.max_timer() + 1) + timer
+    }
+
+    // What a fish in `state` turns into after one day, itself plus any newborn
+    fn successors(&self, state: usize) -> Vec<usize> {
+        let (age, timer) = (state / (self.max_timer() + 1), state % (self.max_timer() + 1));
+        if self.lifespan.is_some_and(|lifespan| age + 1 >= lifespan) {
+            return vec![];
+        }
+        if timer > 0 {
+            return vec![self.state(timer - 1, age + 1)];
+        }
+        let mut next = vec![self.state(self.reset, age + 1)];
+        if age >= self.maturation {
+            next.push(self.state(self.newborn, 0));
+        }
+        next
+    }
+}
+
+// Fish from the population file are treated as freshly matured adults
+fn initial_counts(species: &Species, timers: &[usize]) -> Vec<BigUint> {
+    let mut counts = vec![BigUint::zero(); species.state_count()];
+    for &timer in timers {
+        assert!(timer <= species.max_timer(), "Timer {} is out of range for {}", timer, species.name);
+        counts[species.state(timer, species.maturation)] += 1u32;
+    }
+    counts
+}
+
+// Each line is either bare timers for lanternfish, or "species settings: timers"
+fn parse_population(contents: &str) -> Vec<(Species, Vec<usize>)> {
+    contents.split('\n').filter(|line| !line.trim().is_empty()).map(|line| {
+        let (species, timers) = match line.split_once(':') {
+            Some((header, timers)) => (Species::from_header(header), timers),
+            None => (Species::lanternfish(), line),
+        };
+        let timers = timers.split(',').map(|s| s.trim().parse().unwrap()).collect();
+        (species, timers)
+    }).collect()
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let flag = |name: &str| args.iter().position(|s| s == name).and_then(|i| args.get(i + 1));
+    let filename = if args.iter().any(|s| s == "-s") { "sample.txt" } else { FILENAME };
+    let days: u64 = flag("-d").map_or(256, |d| d.parse().expect("-d takes a number of days"));
+    let series_days: usize = flag("-t").map_or(256, |t| t.parse().expect("-t takes a number of days"));
+    let modulus: Option<BigUint> = flag("-p").map(|p| p.parse().expect("-p takes a modulus"));
+
+    let contents = fs::read_to_string(filename)
+        .unwrap_or_else(|_| panic!("Something went wrong reading the file {}", filename));
+    let population = parse_population(&contents);
+
+    let mut counts: Vec<Vec<BigUint>> = population.iter()
+        .map(|(species, timers)| initial_counts(species, timers))
+        .collect();
     println!("counts: {:?}", counts);
-    for i in 0..256 {
-        better_simulate(&mut counts); 
-        println!("{}: total: {}, counts: {:?}", i + 1, counts.iter().sum::<usize>(), counts);
+    let initial = counts.clone();
+    let mut series = vec![totals(&counts)];
+    for i in 0..series_days {
+        for ((species, _), species_counts) in population.iter().zip(counts.iter_mut()) {
+            better_simulate(species_counts, species);
+        }
+        let day_totals = totals(&counts);
+        let total: BigUint = day_totals.iter().sum();
+        println!("{}: total: {}, by species: {:?}", i + 1, total, day_totals);
+        series.push(day_totals);
+    }
+    if let Some(path) = flag("--csv") {
+        fs::write(path, to_csv(&population, &series))
+            .unwrap_or_else(|_| panic!("Could not write {}", path));
+        println!("Wrote {} days to {}", series_days, path);
     }
 
-    for _i in 0..80 {
-        simulate(& mut fish);
+    let naive: usize = population.iter().map(|(species, timers)| {
+        let mut fish: Vec<(usize, usize)> = timers.iter().map(|&timer| (timer, species.maturation)).collect();
+        for _i in 0..80 {
+            simulate(&mut fish, species);
+        }
+        fish.len()
+    }).sum();
+    println!("Population after 80 days, one fish at a time: {}", naive);
+
+    // The matrix gets big for mortal species, so only forecast when asked
+    if flag("-d").is_none() && modulus.is_none() {
+        return;
+    }
+    let forecasted = population.iter().zip(&initial).fold(BigUint::zero(), |acc, ((species, _), counts)| {
+        acc + forecast(species, counts, days, modulus.as_ref())
+    });
+    let forecasted = reduce(forecasted, modulus.as_ref());
+    match &modulus {
+        Some(p) => println!("Population after {} days mod {}: {}", days, p, forecasted),
+        None => println!("Population after {} days: {}", days, forecasted),
     }
 }
-fn better_simulate(counts: &mut [usize]) {
-    let zeroes = counts[0];
-    for i in 1..counts.len() {
-        counts[i - 1] = counts[i]; 
+
+fn totals(counts: &[Vec<BigUint>]) -> Vec<BigUint> {
+    counts.iter().map(|species_counts| species_counts.iter().sum()).collect()
+}
+
+fn to_csv(population: &[(Species, Vec<usize>)], series: &[Vec<BigUint>]) -> String {
+    let mut csv = String::from("day");
+    for (species, _) in population {
+        csv += ",";
+        csv += &species.name;
+    }
+    csv += ",total\n";
+    for (day, day_totals) in series.iter().enumerate() {
+        csv += &day.to_string();
+        for total in day_totals {
+            csv += &format!(",{}", total);
+        }
+        csv += &format!(",{}\n", day_totals.iter().sum::<BigUint>());
     }
-    counts[6] += zeroes;
-    counts[8] = zeroes;
+    csv
 }
 
-fn simulate(fish: &mut Vec<usize>)  {
+fn better_simulate(counts: &mut Vec<BigUint>, species: &Species) {
+    let mut next = vec![BigUint::zero(); counts.len()];
+    for (state, count) in counts.iter().enumerate().filter(|(_, count)| !count.is_zero()) {
+        for successor in species.successors(state) {
+            next[successor] += count;
+        }
+    }
+    *counts = next;
+}
+
+// Fish are (timer, age)
+fn simulate(fish: &mut Vec<(usize, usize)>, species: &Species)  {
     let mut new_fish = 0;
-    fish.iter_mut().for_each(|f| {
-        *f = match f {
-            0 => {new_fish += 1; 6},
-            _ => *f - 1
+    fish.retain(|(_, age)| species.lifespan.is_none_or(|lifespan| age + 1 < lifespan));
+    fish.iter_mut().for_each(|(timer, age)| {
+        *timer = match timer {
+            0 => {
+                if *age >= species.maturation {
+                    new_fish += 1;
+                }
+                species.reset
+            },
+            _ => *timer - 1
         };
+        *age += 1;
     });
 
     for _ in 0..new_fish {
-        fish.push(8);
+        fish.push((species.newborn, 0));
     };
-}
\ No newline at end of file
+}
+
+// One day of better_simulate as a matrix: next[row] = sum(matrix[row][col] * counts[col])
+fn transition_matrix(species: &Species) -> Matrix {
+    let n = species.state_count();
+    let mut matrix = vec![vec![BigUint::zero(); n]; n];
+    let edges = (0..n).flat_map(|state| species.successors(state).into_iter().map(move |next| (next, state)));
+    for (row, col) in edges {
+        matrix[row][col] += 1u32;
+    }
+    matrix
+}
+
+fn reduce(value: BigUint, modulus: Option<&BigUint>) -> BigUint {
+    match modulus {
+        Some(p) => value % p,
+        None => value,
+    }
+}
+
+// Skips the zeros in `a`, which is most of it until the powers fill in
+fn multiply(a: &Matrix, b: &Matrix, modulus: Option<&BigUint>) -> Matrix {
+    a.iter().map(|a_row| {
+        let mut row = vec![BigUint::zero(); b.len()];
+        for (factor, b_row) in a_row.iter().zip(b).filter(|(factor, _)| !factor.is_zero()) {
+            for (sum, value) in row.iter_mut().zip(b_row) {
+                *sum += factor * value;
+            }
+        }
+        row.into_iter().map(|sum| reduce(sum, modulus)).collect()
+    }).collect()
+}
+
+// Square-and-multiply, O(log days) matrix products
+fn power(matrix: &Matrix, mut exponent: u64, modulus: Option<&BigUint>) -> Matrix {
+    let n = matrix.len();
+    let mut result: Matrix = (0..n).map(|row| {
+        (0..n).map(|col| if row == col { BigUint::one() } else { BigUint::zero() }).collect()
+    }).collect();
+    let mut base = matrix.clone();
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = multiply(&result, &base, modulus);
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            base = multiply(&base, &base, modulus);
+        }
+    }
+    result
+}
+
+// Total fish after `days`. Without a modulus the answer gains a digit roughly
+// every 26 days, so very long horizons are only practical modulo something.
+// Squaring costs states^3 where a day of better_simulate costs about states,
+// so shorter horizons are just simulated.
+fn forecast(species: &Species, counts: &[BigUint], days: u64, modulus: Option<&BigUint>) -> BigUint {
+    let n = species.state_count() as u64;
+    if days < n * n * 64 {
+        let mut counts = counts.to_vec();
+        for _ in 0..days {
+            better_simulate(&mut counts, species);
+            counts = counts.into_iter().map(|count| reduce(count, modulus)).collect();
+        }
+        return reduce(counts.iter().sum(), modulus);
+    }
+    let matrix = power(&transition_matrix(species), days, modulus);
+    let total = matrix.iter().fold(BigUint::zero(), |acc, row| {
+        row.iter().zip(counts).fold(acc, |acc, (factor, count)| acc + factor * count)
+    });
+    reduce(total, modulus)
+}
@@ -1,38 +1,232 @@
 
-use std::{cmp::min, env, fs};
+use std::{env, fs};
+
+#[derive(Debug, Clone, Copy)]
+struct Crab {
+    position: i64,
+    weight: i64,
+}
+
+// A crab in 2D or 3D, written "x,y[,z][:weight]"
+#[derive(Debug, Clone)]
+struct CrabNd {
+    position: Vec<i64>,
+    weight: i64,
+}
+
+impl CrabNd {
+    fn from_line(line: &str) -> CrabNd {
+        let (position, weight) = line.trim().split_once(':').unwrap_or((line.trim(), "1"));
+        let position: Vec<i64> = position.split(',').map(|s| s.trim().parse().unwrap()).collect();
+        assert!((2..=3).contains(&position.len()), "Expected x,y or x,y,z, found {}", line);
+        CrabNd { position, weight: weight.parse().unwrap() }
+    }
+}
+
+// One coordinate of every crab, so separable metrics can reuse `optimize`
+fn project(crabs: &[CrabNd], axis: usize) -> Vec<Crab> {
+    crabs.iter().map(|crab| Crab { position: crab.position[axis], weight: crab.weight }).collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Metric {
+    // Sum of steps along each axis
+    Manhattan,
+    // Longest single-axis distance, so diagonal moves are free
+    Chebyshev,
+    // Triangular fuel along each axis, summed
+    Triangular,
+}
+
+impl Metric {
+    fn fuel(&self, crab: &CrabNd, target: &[i64]) -> i64 {
+        let steps = crab.position.iter().zip(target).map(|(p, t)| (p - t).abs());
+        crab.weight * match self {
+            Metric::Manhattan => steps.sum(),
+            Metric::Chebyshev => steps.max().unwrap(),
+            Metric::Triangular => steps.map(cost).sum(),
+        }
+    }
+
+    fn total_fuel(&self, crabs: &[CrabNd], target: &[i64]) -> i64 {
+        crabs.iter().map(|crab| self.fuel(crab, target)).sum()
+    }
+
+    fn optimize(&self, crabs: &[CrabNd]) -> (Vec<i64>, i64) {
+        let dimensions = crabs[0].position.len();
+        let position: Vec<i64> = match self {
+            Metric::Manhattan => (0..dimensions).map(|axis| weighted_median(&project(crabs, axis))).collect(),
+            Metric::Triangular => (0..dimensions).map(|axis| optimize(&project(crabs, axis), cost).0).collect(),
+            Metric::Chebyshev => {
+                let mut position = vec![0; dimensions];
+                nested_search(crabs, self, &mut position, 0);
+                descend(crabs, self, position)
+            },
+        };
+        let fuel = self.total_fuel(crabs, &position);
+        (position, fuel)
+    }
+}
+
+fn weighted_median(crabs: &[Crab]) -> i64 {
+    let mut sorted = crabs.to_vec();
+    sorted.sort_unstable_by_key(|crab| crab.position);
+    let half = (sorted.iter().map(|crab| crab.weight).sum::<i64>() + 1) / 2;
+    let mut seen = 0;
+    for crab in &sorted {
+        seen += crab.weight;
+        if seen >= half {
+            return crab.position;
+        }
+    }
+    unreachable!()
+}
+
+// Chebyshev isn't separable. Minimizing a convex function over the remaining
+// axes leaves a convex function of this one, so binary search each axis on
+// the slope of the best fuel found by searching the axes after it.
+fn nested_search(crabs: &[CrabNd], metric: &Metric, position: &mut Vec<i64>, axis: usize) -> i64 {
+    if axis == position.len() {
+        return metric.total_fuel(crabs, position);
+    }
+    let best_at = |value: i64, position: &mut Vec<i64>| {
+        position[axis] = value;
+        nested_search(crabs, metric, position, axis + 1)
+    };
+    let mut low = crabs.iter().map(|crab| crab.position[axis]).min().unwrap();
+    let mut high = crabs.iter().map(|crab| crab.position[axis]).max().unwrap();
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if best_at(mid + 1, position) >= best_at(mid, position) {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    best_at(low, position)
+}
+
+// On the integer grid the nested search can stop one step short, so walk to
+// any cheaper neighbour (diagonals included) until there isn't one
+fn descend(crabs: &[CrabNd], metric: &Metric, mut position: Vec<i64>) -> Vec<i64> {
+    let neighbours = (0..3usize.pow(position.len() as u32)).map(|code| {
+        (0..position.len()).map(|axis| (code / 3usize.pow(axis as u32) % 3) as i64 - 1).collect::<Vec<i64>>()
+    }).collect::<Vec<_>>();
+    let mut fuel = metric.total_fuel(crabs, &position);
+    loop {
+        let best = neighbours.iter().map(|delta| {
+            let candidate: Vec<i64> = position.iter().zip(delta).map(|(p, d)| p + d).collect();
+            (metric.total_fuel(crabs, &candidate), candidate)
+        }).min().unwrap();
+        if best.0 >= fuel {
+            return position;
+        }
+        fuel = best.0;
+        position = best.1;
+    }
+}
+
+// Fuel to move one crab a number of steps. All of these are convex and never
+// decrease with distance, which is what `optimize` relies on.
+#[derive(Debug, Clone, Copy)]
+enum Cost {
+    Linear,
+    Triangular,
+    Quadratic,
+    // Each step costs one more than the last, up to this much per step
+    Capped(i64),
+}
+
+impl Cost {
+    fn from_name(name: &str) -> Cost {
+        match name.split_once('=') {
+            Some(("capped", cap)) => Cost::Capped(cap.parse().expect("capped takes a number")),
+            _ => match name {
+                "linear" => Cost::Linear,
+                "triangular" => Cost::Triangular,
+                "quadratic" => Cost::Quadratic,
+                _ => panic!("Unknown cost {}, expected linear, triangular, quadratic or capped=N", name),
+            },
+        }
+    }
+
+    fn fuel(&self, steps: i64) -> i64 {
+        match self {
+            Cost::Linear => steps,
+            Cost::Triangular => cost(steps),
+            Cost::Quadratic => steps * steps,
+            Cost::Capped(cap) => {
+                let ramp = steps.min(*cap);
+                cost(ramp) + (steps - ramp) * cap
+            },
+        }
+    }
+}
 
 fn main() {
-    let (filename, _sample_param)= if env::args().nth(1).map_or(false, |s| s == "-s") {
-        ("sample.txt", 0)
+    let args: Vec<String> = env::args().collect();
+    let filename = if args.iter().any(|s| s == "-s") {
+        "sample.txt"
     } else {
-        ("input.txt", 0)
+        "input.txt"
+    };
+    let costs: Vec<(String, Cost)> = match args.iter().position(|s| s == "-c").and_then(|i| args.get(i + 1)) {
+        Some(name) => vec![(name.clone(), Cost::from_name(name))],
+        None => vec![("part_one".to_string(), Cost::Linear), ("part_two".to_string(), Cost::Triangular)],
     };
 
     let contents = fs::read_to_string(filename)
         .unwrap_or_else(|_| panic!("Something went wrong reading the file {}", filename));
-    let mut input_lines = contents.split('\n');
+    let input_lines: Vec<&str> = contents.split('\n').filter(|line| !line.trim().is_empty()).collect();
 
-    let mut nums: Vec<i32> = input_lines.next().unwrap().split(',').map(|s| s.parse().unwrap()).collect();
-    // println!("nums: {:?}", nums);
-    nums.sort_unstable();
+    // --points reads one crab per line in 2D or 3D, otherwise the input is a
+    // single line of crabs
+    if args.iter().any(|s| s == "--points") {
+        let crabs: Vec<CrabNd> = input_lines.iter().map(|line| CrabNd::from_line(line)).collect();
+        let dimensions = crabs[0].position.len();
+        assert!(crabs.iter().all(|crab| crab.position.len() == dimensions), "Crabs have mixed dimensions");
+        println!("crabs: {}, dimensions: {}", crabs.len(), dimensions);
+        for metric in [Metric::Manhattan, Metric::Chebyshev, Metric::Triangular] {
+            let (position, fuel) = metric.optimize(&crabs);
+            println!("{:?}: position {:?}, fuel {}", metric, position, fuel);
+        }
+        return;
+    }
 
-    let count = nums.len();
-    let mid = count / 2;
-    let median = nums[mid];
-    let float_mean: f64 = nums.iter().sum::<i32>() as f64 / count as f64;
-    let (low_mean, high_mean) = (float_mean.floor() as i32, float_mean.ceil() as i32);
-    println!("float_mean: {}, mean: {:?}, count: {}, mid: {}, median: {}", float_mean, (low_mean, high_mean), count, mid, median);
+    assert!(input_lines.len() == 1, "Expected one line of crabs, pass --points for one 2D or 3D crab per line");
+    // Each crab is "position" or "position:weight"
+    let crabs: Vec<Crab> = input_lines[0].split(',').map(|s| {
+        let (position, weight) = s.trim().split_once(':').unwrap_or((s.trim(), "1"));
+        Crab { position: position.parse().unwrap(), weight: weight.parse().unwrap() }
+    }).collect();
+    println!("crabs: {}, total weight: {}", crabs.len(), crabs.iter().map(|c| c.weight).sum::<i64>());
 
-    let part_one : i32 = nums.iter().map(|num| { (num - median).abs() }).sum();
-    println!("part_one {:?}", part_one);
+    for (name, model) in costs {
+        let (position, fuel) = optimize(&crabs, |steps| model.fuel(steps));
+        println!("{} ({:?}): position {}, fuel {}", name, model, position, fuel);
+    }
+}
 
-    // let part_two : Vec<i32> = nums.iter().map(|num| { cost((num - mean).abs())}).collect();
-    let part_two_low : i32 = nums.iter().map(|num| { cost((num - low_mean).abs())}).sum();
-    let part_two_high : i32 = nums.iter().map(|num| { cost((num - high_mean).abs())}).sum();
-    println!("part_two_low: {:?} part_two_high: {:?}", part_two_low, part_two_high);
-    println!("part_two {}", min(part_two_low, part_two_high));
+fn total_fuel<F>(crabs: &[Crab], target: i64, cost: &F) -> i64 where F: Fn(i64) -> i64 {
+    crabs.iter().map(|crab| crab.weight * cost((crab.position - target).abs())).sum()
 }
 
-fn cost(steps: i32) -> i32 {
+// Finds the leftmost cheapest position for any convex per-crab cost. The total
+// is convex too, so binary search for where its slope stops being negative.
+fn optimize<F>(crabs: &[Crab], cost: F) -> (i64, i64) where F: Fn(i64) -> i64 {
+    let mut low = crabs.iter().map(|crab| crab.position).min().unwrap();
+    let mut high = crabs.iter().map(|crab| crab.position).max().unwrap();
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if total_fuel(crabs, mid + 1, &cost) >= total_fuel(crabs, mid, &cost) {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    (low, total_fuel(crabs, low, &cost))
+}
+
+fn cost(steps: i64) -> i64 {
     (steps * (steps + 1)) / 2
-}
\ No newline at end of file
+}
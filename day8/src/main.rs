@@ -1,18 +1,24 @@
 
-use std::{env, fs, collections::HashMap};
+use std::{env, fs};
 use itertools::Itertools;
 
 
 fn main() {
-    let (filename, _sample_param)= if env::args().nth(1).map_or(false, |s| s == "-s") {
-        ("sample.txt", 0)
+    let args: Vec<String> = env::args().collect();
+    let filename = if args.iter().any(|s| s == "-s") {
+        "sample.txt"
     } else {
-        ("input.txt", 0)
+        "input.txt"
+    };
+    let display = match args.iter().position(|s| s == "-d").and_then(|i| args.get(i + 1)) {
+        Some(path) => Display::from_definition(&fs::read_to_string(path)
+            .unwrap_or_else(|_| panic!("Something went wrong reading the display {}", path))),
+        None => Display::seven_segment(),
     };
 
     let contents = fs::read_to_string(filename)
         .unwrap_or_else(|_| panic!("Something went wrong reading the file {}", filename));
-    let input_lines = contents.split('\n');
+    let input_lines = contents.split('\n').filter(|s| !s.trim().is_empty());
     let rows : Vec<(Vec<String>, Vec<String>)> = input_lines.map(|s| {
         let (input, output) = s.split(" | ")
         .map(|section| {
@@ -27,88 +33,208 @@ fn main() {
     }).collect_vec();
 
     // println!("Rows: {:?}", rows);
-    println!("Part one: {}", part_one(&rows));
-    println!("Part two: {}", part_two(&rows));
+    println!("Part one: {}", part_one(&display, &rows));
+    println!("Part two: {}", part_two(&display, &rows));
 
 
 }
 
-// Count 1s, 4s, 7s, 8s in output section
-fn part_one(rows: &[(Vec<String>, Vec<String>)]) -> usize{
-    let (mut ones, mut fours, mut sevens, mut eights) = (0,0,0,0);
-
-    rows.iter().for_each(|(_input, output)| {
-        ones += output.iter().filter(|s| s.len() == 2).count();
-        fours += output.iter().filter(|s| s.len() == 4).count();
-        sevens += output.iter().filter(|s| s.len() == 3).count();
-        eights += output.iter().filter(|s| s.len() == 7).count();
-    });
-    ones + fours + sevens + eights
+// Count outputs whose length alone identifies the glyph (1, 4, 7 and 8 on a
+// seven segment display)
+fn part_one(display: &Display, rows: &[(Vec<String>, Vec<String>)]) -> usize {
+    let unique_lengths = display.unique_lengths();
+    println!("Uniquely identifiable lengths: {:?}", unique_lengths);
+    rows.iter().map(|(_input, output)| {
+        output.iter().filter(|s| unique_lengths.contains(&s.chars().count())).count()
+    }).sum()
 }
 
-fn string_contains_char(target: &str, c: &char) -> bool {
-    target.contains(|candidate| c == &candidate)
+// A display: its segment names and which segments each glyph lights
+struct Display {
+    segments: Vec<char>,
+    glyphs: Vec<(String, u32)>,
 }
 
-// sum output values 
-fn part_two(rows: &[(Vec<String>, Vec<String>)]) -> usize {
-    let mut sum = 0;
-    rows.iter().for_each(|(input, output)| {
-        let mut known_mappings : HashMap<char, char> = HashMap::new();
-        let mut known_digits : [&str; 10 ]= [""; 10];
-        let empty_string = String::from("");
-
-        // let total_iter = input.iter().chain(output.iter());
-        known_digits[1] = input.iter().find(|s| s.len() == 2).unwrap_or(&empty_string);
-        known_digits[4] = input.iter().find(|s| s.len() == 4).unwrap_or(&empty_string);
-        known_digits[7] = input.iter().find(|s| s.len() == 3).unwrap_or(&empty_string);
-        known_digits[8] = input.iter().find(|s| s.len() == 7).unwrap_or(&empty_string);
-
-        
-        // Anything not common between 1 and 7 is the top segment A
-        known_mappings.insert('a', known_digits[7].chars().find(|c| string_contains_char(known_digits[1], c)).unwrap());
-
-        //3 is 7 plus two letters
-        known_digits[3] = input.iter().filter(|s| s.len() == 5 && !known_digits.contains(&s.as_str())).find(|s| 
-            s.chars().filter(|c| 
-                !string_contains_char(known_digits[7], c)).count() == 2).unwrap();
-        
-        // anything shared between 3,4 and 8 that is not in 1 is middle segment D
-        known_mappings.insert('d', known_digits[8].chars().find(|c| 
-            string_contains_char(known_digits[3], c) && 
-            string_contains_char(known_digits[4], c) && 
-            !string_contains_char(known_digits[1], c))
-        .unwrap());
-        
-        // zero is 8 - middle
-        let zero_string = known_digits[8].chars().filter(|c| c != &known_mappings[&'d']).collect::<String>();
-        known_digits[0] = &zero_string;
-
-        // 9 has length 6, is not in the list, and contains all elements of 1
-        known_digits[9] = input.iter().filter(|s| s.len() == 6 && !known_digits.contains(&s.as_str())).find( |s|
-            known_digits[1].chars().all(|c| 
-            string_contains_char(s, &c))
-        ).unwrap();
-
-        // 6 is the last known element of length 6
-        known_digits[6] = input.iter().find(|s| s.len() == 6 && !known_digits.contains(&s.as_str())).unwrap();
-
-        // 5 is 6 but missing an element
-        known_digits[5] = input.iter().filter(|s| s.len() == 5 && !known_digits.contains(&s.as_str())).find( |s|
-            known_digits[6].chars().filter(|c| 
-            !string_contains_char(s, c)).count() == 1
-        ).unwrap();
-        known_digits[2] = input.iter().find(|s| s.len() == 5 && !known_digits.contains(&s.as_str())).unwrap();
-        println!("input: {:?} | {:?} ", input, output);
-        println!("known_digits: {:?}\nknown_mappings: {:?}", known_digits, known_mappings);
-        let output_value :usize = output.iter().map(|s|
-            known_digits.iter().find_position(|mapping|
-                mapping == &&s.as_str()
-            ).unwrap().0.to_string()).collect::<String>().parse().unwrap();
-        println!("output: {:?}", output_value);
-        sum += output_value
-    });
+impl Display {
+    fn seven_segment() -> Display {
+        let glyphs = ["abcefg", "cf", "acdeg", "acdfg", "bcdf", "abdfg", "abdefg", "acf", "abcdefg", "abcdfg"];
+        let segments: Vec<char> = "abcdefg".chars().collect();
+        let mut display = Display { segments, glyphs: vec![] };
+        display.glyphs = glyphs.iter().enumerate()
+            .map(|(digit, lit)| (digit.to_string(), display.mask(lit).unwrap()))
+            .collect();
+        display
+    }
+
+    // One "segments: <names>" line, then a "<glyph>: <lit segments>" line per
+    // glyph. Blank lines and lines starting with # are skipped.
+    fn from_definition(definition: &str) -> Display {
+        let mut lines = definition.split('\n')
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.split_once(':').unwrap_or_else(|| panic!("Expected name: segments, found {}", line)))
+            .map(|(name, lit)| (name.trim(), lit.trim()));
+        let segments: Vec<char> = match lines.next() {
+            Some(("segments", names)) => names.chars().filter(|c| !c.is_whitespace()).collect(),
+            _ => panic!("Display definition must start with a segments line"),
+        };
+        assert!(!segments.is_empty(), "The segments line doesn't name any segments");
+        assert!(segments.len() <= 32, "At most 32 segments are supported");
+        assert!(segments.iter().unique().count() == segments.len(), "Segment names must be unique");
+
+        let mut display = Display { segments, glyphs: vec![] };
+        for (name, lit) in lines {
+            let mask = display.mask(lit)
+                .unwrap_or_else(|| panic!("Glyph {} lights a segment that isn't on the display", name));
+            if let Some((other, _)) = display.glyphs.iter().find(|(_, glyph)| *glyph == mask) {
+                panic!("Glyphs {} and {} light the same segments", other, name);
+            }
+            display.glyphs.push((name.to_string(), mask));
+        }
+        display
+    }
+
+    // Segment counts that only one glyph has
+    fn unique_lengths(&self) -> Vec<usize> {
+        let lengths: Vec<usize> = self.glyphs.iter().map(|(_, glyph)| glyph.count_ones() as usize).collect();
+        lengths.iter()
+            .filter(|length| lengths.iter().filter(|other| other == length).count() == 1)
+            .copied()
+            .sorted()
+            .collect()
+    }
+
+    // Bitmask of the named segments, None if a name isn't on this display
+    fn mask(&self, pattern: &str) -> Option<u32> {
+        pattern.chars().try_fold(0, |acc, c| {
+            self.segments.iter().position(|s| *s == c).map(|i| acc | 1 << i)
+        })
+    }
+
+    fn all(&self) -> u32 {
+        u32::MAX >> (32 - self.segments.len())
+    }
+}
 
-    sum
+#[derive(Debug, PartialEq, Eq)]
+enum DecodeError {
+    // No wiring explains every pattern
+    Inconsistent,
+    // Several wirings fit the patterns but read the output differently
+    Ambiguous(Vec<String>),
+}
+
+// Maps a wire bitmask through a wire -> segment assignment
+fn rewire(pattern: u32, wiring: &[usize]) -> u32 {
+    wiring.iter().enumerate()
+        .filter(|(wire, _)| pattern >> wire & 1 == 1)
+        .fold(0, |acc, (_, segment)| acc | 1 << segment)
+}
+
+// Narrows the segments each wire could drive. A pattern must be one of the
+// glyphs with the same number of segments that its wires could still light,
+// so its wires are limited to those glyphs' segments and every other wire to
+// the rest. Returns false once some wire has nowhere left to go.
+fn propagate(display: &Display, patterns: &[u32], domains: &mut [u32]) -> bool {
+    loop {
+        let before = domains.to_vec();
+        for &pattern in patterns {
+            let fits = |glyph: u32| (0..domains.len()).all(|wire| {
+                let allowed = if pattern >> wire & 1 == 1 { glyph } else { !glyph };
+                domains[wire] & allowed != 0
+            });
+            let candidates: Vec<u32> = display.glyphs.iter()
+                .map(|(_, glyph)| *glyph)
+                .filter(|glyph| glyph.count_ones() == pattern.count_ones() && fits(*glyph))
+                .collect();
+            let lit = candidates.iter().fold(0, |acc, glyph| acc | glyph);
+            let unlit = candidates.iter().fold(0, |acc, glyph| acc | (!glyph & display.all()));
+            for (wire, domain) in domains.iter_mut().enumerate() {
+                *domain &= if pattern >> wire & 1 == 1 { lit } else { unlit };
+            }
+        }
+        // A wire pinned to one segment rules it out for every other wire
+        for wire in 0..domains.len() {
+            if domains[wire].count_ones() == 1 {
+                let pinned = domains[wire];
+                for (other, domain) in domains.iter_mut().enumerate() {
+                    if other != wire {
+                        *domain &= !pinned;
+                    }
+                }
+            }
+        }
+        if domains.contains(&0) {
+            return false;
+        }
+        if domains == before.as_slice() {
+            return true;
+        }
+    }
+}
 
-}
\ No newline at end of file
+// Backtracks over wire assignments, collecting every distinct reading of the
+// output. Stops early once two readings disagree.
+fn search(display: &Display, patterns: &[u32], output: &[u32], domains: Vec<u32>, readings: &mut Vec<String>) {
+    if readings.len() > 1 {
+        return;
+    }
+    let mut domains = domains;
+    if !propagate(display, patterns, &mut domains) {
+        return;
+    }
+    match domains.iter().position(|domain| domain.count_ones() > 1) {
+        Some(wire) => {
+            for segment in (0..display.segments.len()).filter(|s| domains[wire] >> s & 1 == 1) {
+                let mut guess = domains.clone();
+                guess[wire] = 1 << segment;
+                search(display, patterns, output, guess, readings);
+            }
+        },
+        None => {
+            let wiring: Vec<usize> = domains.iter().map(|domain| domain.trailing_zeros() as usize).collect();
+            let glyph = |pattern: &u32| {
+                let lit = rewire(*pattern, &wiring);
+                display.glyphs.iter().find(|(_, glyph)| *glyph == lit).map(|(name, _)| name.clone())
+            };
+            if patterns.iter().all(|pattern| glyph(pattern).is_some()) {
+                let reading: String = output.iter().map(|pattern| glyph(pattern).unwrap()).collect();
+                if !readings.contains(&reading) {
+                    readings.push(reading);
+                }
+            }
+        },
+    }
+}
+
+fn decode(display: &Display, input: &[String], output: &[String]) -> Result<String, DecodeError> {
+    let to_masks = |patterns: &[String]| -> Result<Vec<u32>, DecodeError> {
+        patterns.iter().map(|s| display.mask(s).ok_or(DecodeError::Inconsistent)).collect()
+    };
+    let output = to_masks(output)?;
+    let mut patterns = to_masks(input)?;
+    patterns.extend(&output);
+
+    let mut readings = vec![];
+    search(display, &patterns, &output, vec![display.all(); display.segments.len()], &mut readings);
+    match readings.len() {
+        0 => Err(DecodeError::Inconsistent),
+        1 => Ok(readings.pop().unwrap()),
+        _ => Err(DecodeError::Ambiguous(readings)),
+    }
+}
+
+// sum output values, any reading that isn't a number is printed but not summed
+fn part_two(display: &Display, rows: &[(Vec<String>, Vec<String>)]) -> usize {
+    let mut sum = 0;
+    for (line, (input, output)) in rows.iter().enumerate() {
+        match decode(display, input, output) {
+            Ok(reading) => {
+                println!("output: {}", reading);
+                sum += reading.parse::<usize>().unwrap_or(0);
+            },
+            Err(error) => println!("line {}: {:?} | {:?} is {:?}", line + 1, input, output, error),
+        }
+    }
+    sum
+}
@@ -1,105 +1,338 @@
 
-use std::{env, fs};
+use std::{collections::VecDeque, env, fs};
 use itertools::Itertools;
-use std::collections::HashSet;
 
 fn main() {
-    let (filename, _sample_param)= if env::args().nth(1).map_or(false, |s| s == "-s") {
-        ("sample.txt", 0)
+    let args: Vec<String> = env::args().collect();
+    let filename = if args.iter().any(|s| s == "-s") {
+        "sample.txt"
     } else {
-        ("input.txt", 0)
+        "input.txt"
     };
+    // Cells this high or higher separate basins
+    let wall: u32 = args.iter().position(|s| s == "-w")
+        .and_then(|i| args.get(i + 1))
+        .map_or(9, |w| w.parse().expect("-w takes a height"));
 
     let contents = fs::read_to_string(filename)
         .unwrap_or_else(|_| panic!("Something went wrong reading the file {}", filename));
-    let input_lines = contents.split('\n');
-    let input = input_lines.map(|line| line.chars().map(|c| c.to_digit(10).unwrap()).collect_vec()).collect_vec();
- 
+    let input_lines = contents.split('\n').filter(|line| !line.trim().is_empty());
+    // Puzzle input is one digit per height, other rasters separate heights
+    let input = input_lines.map(|line| {
+        if line.contains(|c: char| c.is_whitespace() || c == ',') {
+            line.split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse().unwrap())
+                .collect_vec()
+        } else {
+            line.chars().map(|c| c.to_digit(10).unwrap()).collect_vec()
+        }
+    }).collect_vec();
+    let heightmap = Heightmap::new(&input);
+
     // println!("{:?}", input);
-    println!("part one: {}", part_one(&input));
-    println!("part two: {}", part_two(&input));
+    println!("part one: {}", part_one(&heightmap));
+    println!("part two: {}", part_two(&heightmap, wall, filename == "sample.txt"));
+
+    let drains = drainage(&heightmap);
+    let labels = flow_basins(&heightmap, &drains);
+    let basin_count = labels.iter().max().map_or(0, |label| label + 1);
+    let saddles = saddles(&heightmap);
+    let ridges = ridges(&heightmap, &labels);
+    println!("flow basins: {}, saddles: {}, ridge cells: {}", basin_count, saddles.len(), ridges.len());
+    let mut flow_sizes = vec![0; basin_count];
+    for label in &labels {
+        flow_sizes[*label] += 1;
+    }
+    println!("largest flow basins: {:?}", flow_sizes.iter().sorted().rev().take(3).collect_vec());
+    if filename == "sample.txt" {
+        print_drainage(&heightmap, &drains);
+    }
+    if let Some(path) = args.iter().position(|s| s == "--image").and_then(|i| args.get(i + 1)) {
+        fs::write(path, labeled_image(&heightmap, &labels, &ridges, &saddles))
+            .unwrap_or_else(|_| panic!("Could not write {}", path));
+        println!("Wrote {}", path);
+    }
 }
 
-fn score(input: &[Vec<u32>], row_index: usize, column_index: usize, value: &u32) -> u32{
-    let pts = [(row_index.checked_sub(1), Some(column_index)), (Some(row_index+1), Some(column_index)), (Some(row_index), Some(column_index+1)), (Some(row_index), column_index.checked_sub(1))];
-    if pts.iter().all(|(x, y)| x.map_or(true, |x| y.map_or(true, |y| input.get(x).map_or(true, |row| row.get(y).map_or(true, |candidate| value < candidate))))) {
-        value + 1
-    } else {
-        0
+// Dense row-major grid of heights
+struct Heightmap {
+    width: usize,
+    height: usize,
+    cells: Vec<u32>,
+}
+
+impl Heightmap {
+    fn new(input: &[Vec<u32>]) -> Heightmap {
+        let width = input.first().map_or(0, |row| row.len());
+        assert!(input.iter().all(|row| row.len() == width), "Rows have different lengths");
+        Heightmap { width, height: input.len(), cells: input.concat() }
+    }
+
+    fn position(&self, index: usize) -> (usize, usize) {
+        (index / self.width, index % self.width)
+    }
+
+    // Indices of the up, down, left and right neighbours that exist
+    fn neighbors(&self, index: usize) -> impl Iterator<Item = usize> {
+        let (row, col) = self.position(index);
+        let (width, height) = (self.width, self.height);
+        Iterator::flatten(IntoIterator::into_iter([
+            (row > 0).then(|| index - width),
+            (row + 1 < height).then(|| index + width),
+            (col > 0).then(|| index - 1),
+            (col + 1 < width).then(|| index + 1),
+        ]))
+    }
+
+    fn is_low_point(&self, index: usize) -> bool {
+        self.neighbors(index).all(|neighbor| self.cells[index] < self.cells[neighbor])
+    }
+}
+
+fn part_one(heightmap: &Heightmap) -> u32 {
+    (0..heightmap.cells.len())
+        .filter(|&index| heightmap.is_low_point(index))
+        .map(|index| heightmap.cells[index] + 1)
+        .sum()
+}
+
+struct UnionFind {
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(count: usize) -> UnionFind {
+        UnionFind { parents: (0..count).collect(), sizes: vec![1; count] }
+    }
+
+    fn find(&mut self, mut index: usize) -> usize {
+        while self.parents[index] != index {
+            self.parents[index] = self.parents[self.parents[index]];
+            index = self.parents[index];
+        }
+        index
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        let (big, small) = if self.sizes[a] >= self.sizes[b] { (a, b) } else { (b, a) };
+        self.parents[small] = big;
+        self.sizes[big] += self.sizes[small];
     }
 }
 
-fn part_one(input: &[Vec<u32>]) -> u32 {
-    input.iter().enumerate().fold(0, |acc: u32, (row_index, row)| {
-        acc + row.iter().enumerate().map(|(column_index, value)| {
-            score(input, row_index, column_index, value) 
-        }).sum::<u32>()
-    })
+#[derive(Debug)]
+struct Basin {
+    // (row, col) of every cell, in row-major order
+    cells: Vec<(usize, usize)>,
+    size: usize,
+    // (row, col) of the lowest cell, the first one if several tie
+    low_point: (usize, usize),
 }
 
-fn get_neighbor_indices(x: &usize, y: &usize) -> HashSet<(usize, usize)> {
-    let mut out = HashSet::new();
-    let x_plus= x + 1;
-    let y_plus  = y + 1;
-    let x_minus = x.checked_sub(1);
-    let y_minus = y.checked_sub(1);
-    out.insert((x_plus, *y));
-    out.insert((*x, y_plus));
-    if let Some(x_)= x_minus {
-        out.insert((x_, *y));
-    }
-    if let Some(y_)= y_minus {
-        out.insert((*x, y_));
-    }
-    // println!("Neighbors to ({},{}), {:?}", x, y, out);
-    out
+// Labels every connected region of cells below `wall` in one pass: each cell
+// is joined with its right and lower neighbours, then cells are grouped by root
+fn basins(heightmap: &Heightmap, wall: u32) -> Vec<Basin> {
+    let open = |index: usize| heightmap.cells[index] < wall;
+    let mut regions = UnionFind::new(heightmap.cells.len());
+    for index in (0..heightmap.cells.len()).filter(|&index| open(index)) {
+        let (row, col) = heightmap.position(index);
+        if col + 1 < heightmap.width && open(index + 1) {
+            regions.union(index, index + 1);
+        }
+        if row + 1 < heightmap.height && open(index + heightmap.width) {
+            regions.union(index, index + heightmap.width);
+        }
+    }
+
+    let mut labels: Vec<Option<usize>> = vec![None; heightmap.cells.len()];
+    let mut basins: Vec<Basin> = Vec::new();
+    for index in (0..heightmap.cells.len()).filter(|&index| open(index)) {
+        let root = regions.find(index);
+        let label = *labels[root].get_or_insert_with(|| {
+            basins.push(Basin { cells: vec![], size: 0, low_point: heightmap.position(index) });
+            basins.len() - 1
+        });
+        let basin = &mut basins[label];
+        let (low_row, low_col) = basin.low_point;
+        if heightmap.cells[index] < heightmap.cells[low_row * heightmap.width + low_col] {
+            basin.low_point = heightmap.position(index);
+        }
+        basin.cells.push(heightmap.position(index));
+        basin.size += 1;
+    }
+    basins
 }
 
-fn part_two(input: &[Vec<u32>]) -> usize {
-    let low_points = input.iter().enumerate().filter_map(|(row_index, row)| {
-        let filtered_row = row.iter().enumerate().filter_map(|(column_index, value)| {
-            if score(input, row_index, column_index, value) != 0 {
-                Some((row_index, column_index, value))
-            } else {
-                None
+fn part_two(heightmap: &Heightmap, wall: u32, verbose: bool) -> usize {
+    let basins = basins(heightmap, wall);
+    if verbose {
+        for basin in &basins {
+            println!("Basin at {:?} has size {}", basin.low_point, basin.size);
+        }
+    }
+    basins.iter().map(|basin| basin.size).sorted().rev().take(3).product()
+}
+
+// Where each cell drains: its lowest neighbour if that is lower than the cell,
+// the first one on ties. Cells on a flat with no lower neighbour are routed
+// across the flat towards its lowest outlet, so only flats with no way down
+// anywhere on their edge are left as sinks (None).
+fn drainage(heightmap: &Heightmap) -> Vec<Option<usize>> {
+    let mut drains: Vec<Option<usize>> = (0..heightmap.cells.len()).map(|index| {
+        heightmap.neighbors(index)
+            .filter(|&neighbor| heightmap.cells[neighbor] < heightmap.cells[index])
+            .min_by_key(|&neighbor| heightmap.cells[neighbor])
+    }).collect();
+
+    let mut flats = UnionFind::new(heightmap.cells.len());
+    for index in 0..heightmap.cells.len() {
+        for neighbor in heightmap.neighbors(index) {
+            if heightmap.cells[neighbor] == heightmap.cells[index] {
+                flats.union(index, neighbor);
             }
-        }).collect_vec();
-        if !filtered_row.is_empty() {
-            Some(filtered_row)
-        } else {
-            None
         }
-    }).flatten().collect_vec();
-
-    // println!("low_points {:?}", low_points);
-    let regions = low_points.iter().map(|(x, y, _value)| {
-        let mut pts = HashSet::new();
-        // println!("Examining pt {},{} with value {}", x, y, value);
-        pts.insert((*x, *y));
-        let mut last_size = 0;
-        while last_size < pts.len() {
-            last_size = pts.len();
-            let neighbors = pts.iter().fold(HashSet::new(), |acc, (x, y)| {
-                let pt_neighbors = get_neighbor_indices(x, y).into_iter().filter(|(x, y)| {
-                    input.get(*x).map_or(false, |row| 
-                        row.get(*y).map_or(false, |candidate|  {
-                            // println!("candidate: {:?}", candidate);
-                            *candidate != 9
-                        }
-                        )
-                    )
-                }).collect();
-                // println!("valid neighbors: {:?}", pt_neighbors);
-                acc.union(&pt_neighbors).copied().collect()
-            });
-            pts = pts.union(&neighbors).copied().collect();
-
-            // println!("pts_len: {}, pts: {:?}", pts.len(), pts);
+    }
+    let roots = (0..heightmap.cells.len()).map(|index| flats.find(index)).collect_vec();
+    let regions = (0..heightmap.cells.len()).map(|index| (roots[index], index)).into_group_map();
+
+    for cells in regions.values() {
+        let outlet = cells.iter().filter_map(|&index| drains[index].map(|next| heightmap.cells[next])).min();
+        let outlet = match outlet {
+            Some(outlet) => outlet,
+            None => continue,
+        };
+        // Breadth first from the cells next to the lowest outlet. Cells that
+        // already have a way down keep it, the rest drain to the cell they
+        // were reached from.
+        let mut queue: VecDeque<usize> = cells.iter().copied()
+            .filter(|&index| drains[index].is_some_and(|next| heightmap.cells[next] == outlet))
+            .collect();
+        let mut reached = vec![false; heightmap.cells.len()];
+        for &index in &queue {
+            reached[index] = true;
         }
-        pts
-    }).collect_vec();
-    // println!("regions {:?}", regions);
-    regions.iter().map(|set| set.len()).sorted().rev().take(3).product()
+        while let Some(index) = queue.pop_front() {
+            for neighbor in heightmap.neighbors(index).collect_vec() {
+                if roots[neighbor] == roots[index] && !reached[neighbor] {
+                    reached[neighbor] = true;
+                    drains[neighbor].get_or_insert(index);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+    drains
+}
 
+// Labels every cell with the sink it flows into, numbered 0.. in row-major
+// order of first appearance. Sinks joined by a flat of equal height are one
+// basin.
+fn flow_basins(heightmap: &Heightmap, drains: &[Option<usize>]) -> Vec<usize> {
+    let mut sinks = UnionFind::new(heightmap.cells.len());
+    for index in (0..heightmap.cells.len()).filter(|&index| drains[index].is_none()) {
+        for neighbor in heightmap.neighbors(index) {
+            if drains[neighbor].is_none() && heightmap.cells[neighbor] == heightmap.cells[index] {
+                sinks.union(index, neighbor);
+            }
+        }
+    }
 
+    // Follow each cell downhill, remembering the sink for every cell on the way
+    let mut sink_of: Vec<Option<usize>> = vec![None; heightmap.cells.len()];
+    for start in 0..heightmap.cells.len() {
+        let mut path = vec![];
+        let mut index = start;
+        let sink = loop {
+            if let Some(sink) = sink_of[index] {
+                break sink;
+            }
+            path.push(index);
+            match drains[index] {
+                Some(next) => index = next,
+                None => break sinks.find(index),
+            }
+        };
+        for index in path {
+            sink_of[index] = Some(sink);
+        }
+    }
+
+    let mut labels_by_sink: Vec<Option<usize>> = vec![None; heightmap.cells.len()];
+    let mut count = 0;
+    sink_of.into_iter().map(|sink| {
+        *labels_by_sink[sink.unwrap()].get_or_insert_with(|| {
+            count += 1;
+            count - 1
+        })
+    }).collect()
+}
+
+// Interior cells where the heights around them go up, down, up, down: both
+// vertical neighbours higher and both horizontal ones lower, or the reverse
+fn saddles(heightmap: &Heightmap) -> Vec<usize> {
+    let (width, height) = (heightmap.width, heightmap.height);
+    (0..heightmap.cells.len()).filter(|&index| {
+        let (row, col) = heightmap.position(index);
+        if row == 0 || col == 0 || row + 1 == height || col + 1 == width {
+            return false;
+        }
+        let value = heightmap.cells[index];
+        let above = |other: usize| heightmap.cells[other] > value;
+        let below = |other: usize| heightmap.cells[other] < value;
+        let (up, down, left, right) = (index - width, index + width, index - 1, index + 1);
+        (above(up) && above(down) && below(left) && below(right))
+            || (below(up) && below(down) && above(left) && above(right))
+    }).collect()
+}
+
+// Cells on the divide between flow basins: next to a cell of another basin
+// and at least as high as it
+fn ridges(heightmap: &Heightmap, labels: &[usize]) -> Vec<usize> {
+    (0..heightmap.cells.len()).filter(|&index| {
+        heightmap.neighbors(index).any(|neighbor| {
+            labels[neighbor] != labels[index] && heightmap.cells[index] >= heightmap.cells[neighbor]
+        })
+    }).collect()
+}
+
+fn print_drainage(heightmap: &Heightmap, drains: &[Option<usize>]) {
+    for row in 0..heightmap.height {
+        let line: String = (0..heightmap.width).map(|col| {
+            let index = row * heightmap.width + col;
+            match drains[index] {
+                None => 'o',
+                Some(next) if next + heightmap.width == index => '^',
+                Some(next) if next == index + heightmap.width => 'v',
+                Some(next) if next + 1 == index => '<',
+                Some(_) => '>',
+            }
+        }).collect();
+        println!("{}", line);
+    }
+}
+
+// Plain text PPM: each flow basin gets its own colour, ridges are black and
+// saddles white
+fn labeled_image(heightmap: &Heightmap, labels: &[usize], ridges: &[usize], saddles: &[usize]) -> String {
+    let mut colors: Vec<(usize, usize, usize)> = labels.iter()
+        .map(|label| (64 + label * 97 % 192, 64 + label * 57 % 192, 64 + label * 139 % 192))
+        .collect();
+    for &index in ridges {
+        colors[index] = (0, 0, 0);
+    }
+    for &index in saddles {
+        colors[index] = (255, 255, 255);
+    }
+    let mut image = format!("P3\n{} {}\n255\n", heightmap.width, heightmap.height);
+    for row in colors.chunks(heightmap.width) {
+        image += &row.iter().map(|(r, g, b)| format!("{} {} {}", r, g, b)).join(" ");
+        image += "\n";
+    }
+    image
 }